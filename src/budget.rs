@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/* weight of a new reading in the learned draw of a receptacle */
+const DRAW_WEIGHT: f32 = 0.25;
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum BudgetMode {
+    Off,
+    /* reject enable commands that would exceed the branch's alarm threshold */
+    Refuse,
+    /* retry them until the branch has enough headroom */
+    Queue,
+}
+
+impl std::str::FromStr for BudgetMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(BudgetMode::Off),
+            "refuse" => Ok(BudgetMode::Refuse),
+            "queue" => Ok(BudgetMode::Queue),
+            _ => Err(format!("unknown mode \"{}\", expected off, refuse or queue", s)),
+        }
+    }
+}
+
+#[derive(Copy,Clone,Default,Debug)]
+struct Branch {
+    current: f32,
+    /* headroom until the over-current alarm threshold */
+    available: f32,
+}
+
+#[derive(Copy,Clone,Default,Debug)]
+struct Receptacle {
+    enabled: bool,
+    /* average current while switched on, in A */
+    draw: Option<f32>,
+}
+
+/* live branch load and the typical draw of each receptacle, learned from polling */
+pub struct LoadBudget {
+    pub mode: BudgetMode,
+    pub queue_timeout: Duration,
+    branches: Mutex<HashMap<(u8, u8), Branch>>,
+    receptacles: Mutex<HashMap<(u8, u8, u8), Receptacle>>,
+}
+
+impl LoadBudget {
+    pub fn new(mode: BudgetMode, queue_timeout: Duration) -> Self {
+        LoadBudget { mode, queue_timeout, branches: Mutex::new(HashMap::new()), receptacles: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn update_branch(self: &Self, pdu: u8, branch: u8, current: f32, available: f32) -> () {
+        self.branches.lock().unwrap().insert((pdu, branch), Branch { current, available });
+    }
+
+    pub fn update_receptacle(self: &Self, pdu: u8, branch: u8, receptacle: u8, enabled: bool, current: f32) -> () {
+        let mut receptacles = self.receptacles.lock().unwrap();
+        let r = receptacles.entry((pdu, branch, receptacle)).or_default();
+        r.enabled = enabled;
+
+        /* idle devices would teach a draw of zero */
+        if enabled && current > 0.0 {
+            r.draw = Some(match r.draw {
+                Some(draw) => draw + (current - draw) * DRAW_WEIGHT,
+                None => current,
+            });
+        }
+    }
+
+    /* learned draw of a switched off receptacle, nothing to check otherwise */
+    fn draw(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> Option<f32> {
+        let r = self.receptacles.lock().unwrap().get(&(pdu, branch, receptacle)).copied().unwrap_or_default();
+        r.draw.filter(|_| !r.enabled)
+    }
+
+    /* reserves the draw of all receptacles until the next branch poll, so enables in a row add up,
+     * nothing is reserved if any of them exceeds its branch's headroom */
+    pub fn check(self: &Self, receptacles: &[(u8, u8, u8)]) -> Result<(), String> {
+        if self.mode == BudgetMode::Off {
+            return Ok(());
+        }
+
+        let mut branches = self.branches.lock().unwrap();
+        let mut reserved : HashMap<(u8, u8), f32> = HashMap::new();
+
+        for (pdu, branch, receptacle) in receptacles {
+            let draw = match self.draw(*pdu, *branch, *receptacle) {
+                Some(draw) => draw,
+                None => continue,
+            };
+
+            let b = match branches.get(&(*pdu, *branch)) {
+                Some(b) => b,
+                None => continue,
+            };
+
+            let total = reserved.entry((*pdu, *branch)).or_insert(0.0);
+            if *total + draw > b.available {
+                return Err(format!("branch load {:.2}A leaves {:.2}A until the alarm threshold, receptacle {}.{}.{} typically draws {:.2}A", b.current + *total, b.available - *total, pdu, branch, receptacle, draw));
+            }
+            *total += draw;
+        }
+
+        for (id, draw) in reserved {
+            if let Some(b) = branches.get_mut(&id) {
+                b.current += draw;
+                b.available -= draw;
+            }
+        }
+
+        Ok(())
+    }
+
+    /* returns the reservation of a receptacle that could not be switched on */
+    pub fn release(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> () {
+        if self.mode == BudgetMode::Off {
+            return;
+        }
+
+        if let (Some(draw), Some(b)) = (self.draw(pdu, branch, receptacle), self.branches.lock().unwrap().get_mut(&(pdu, branch))) {
+            b.current -= draw;
+            b.available += draw;
+        }
+    }
+
+    /* check for enables running in the background, in queue mode it retries until the deadline */
+    pub async fn wait(self: &Self, receptacles: &[(u8, u8, u8)], deadline: Instant, interval: Duration) -> Result<(), String> {
+        loop {
+            match self.check(receptacles) {
+                Err(_) if self.mode == BudgetMode::Queue && Instant::now() < deadline => {},
+                result => return result,
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(mode: BudgetMode) -> LoadBudget {
+        let budget = LoadBudget::new(mode, Duration::from_secs(300));
+        budget.update_branch(1, 1, 10.0, 6.0);
+        budget.update_receptacle(1, 1, 1, true, 2.0);
+        budget.update_receptacle(1, 1, 1, false, 0.0);
+        budget.update_receptacle(1, 1, 2, true, 3.0);
+        budget.update_receptacle(1, 1, 2, false, 0.0);
+        budget
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!("queue".parse::<BudgetMode>(), Ok(BudgetMode::Queue));
+        assert!("on".parse::<BudgetMode>().is_err());
+    }
+
+    #[test]
+    fn learned_draw() {
+        let budget = budget(BudgetMode::Refuse);
+        budget.update_receptacle(1, 1, 1, true, 4.0);
+        budget.update_receptacle(1, 1, 1, true, 0.0);
+        assert_eq!(budget.draw(1, 1, 1), None);
+        budget.update_receptacle(1, 1, 1, false, 0.0);
+        assert_eq!(budget.draw(1, 1, 1), Some(2.5));
+        assert_eq!(budget.draw(1, 1, 3), None);
+    }
+
+    #[test]
+    fn reservations_add_up() {
+        let budget = budget(BudgetMode::Refuse);
+        assert_eq!(budget.check(&[(1, 1, 1)]), Ok(()));
+        assert_eq!(budget.check(&[(1, 1, 2)]), Ok(()));
+        assert!(budget.check(&[(1, 1, 1)]).is_err());
+
+        budget.release(1, 1, 2);
+        assert_eq!(budget.check(&[(1, 1, 1)]), Ok(()));
+    }
+
+    #[test]
+    fn whole_set() {
+        let budget = budget(BudgetMode::Refuse);
+        budget.update_receptacle(1, 1, 3, true, 2.0);
+        budget.update_receptacle(1, 1, 3, false, 0.0);
+
+        /* each fits on its own, but not all three */
+        let e = budget.check(&[(1, 1, 1), (1, 1, 2), (1, 1, 3)]).unwrap_err();
+        assert!(e.contains("receptacle 1.1.3"), "{}", e);
+
+        /* nothing was reserved by the refused set */
+        assert_eq!(budget.check(&[(1, 1, 1), (1, 1, 2)]), Ok(()));
+    }
+
+    #[test]
+    fn unknown_and_off() {
+        let budget = budget(BudgetMode::Refuse);
+        assert_eq!(budget.check(&[(1, 2, 1), (1, 1, 4)]), Ok(()));
+
+        let budget = LoadBudget::new(BudgetMode::Off, Duration::from_secs(300));
+        budget.update_branch(1, 1, 10.0, 0.0);
+        budget.update_receptacle(1, 1, 1, true, 2.0);
+        budget.update_receptacle(1, 1, 1, false, 0.0);
+        assert_eq!(budget.check(&[(1, 1, 1)]), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn wait_until_deadline() {
+        let queue = budget(BudgetMode::Queue);
+        queue.update_branch(1, 1, 15.0, 1.0);
+        let result = queue.wait(&[(1, 1, 1)], Instant::now() + Duration::from_millis(50), Duration::from_millis(10)).await;
+        assert!(result.is_err());
+
+        let refuse = budget(BudgetMode::Refuse);
+        refuse.update_branch(1, 1, 15.0, 1.0);
+        let start = Instant::now();
+        assert!(refuse.wait(&[(1, 1, 1)], start + Duration::from_secs(60), Duration::from_secs(1)).await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
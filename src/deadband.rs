@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/* changes up to the larger of both limits are suppressed */
+#[derive(Copy,Clone,Default,Debug)]
+struct Band {
+    absolute: f64,
+    percent: f64,
+}
+
+impl std::str::FromStr for Band {
+    type Err = String;
+
+    /* e.g. "50", "2%" or "50 2%" */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut band = Band::default();
+
+        for part in s.split_whitespace() {
+            if let Some(percent) = part.strip_suffix('%') {
+                band.percent = percent.parse::<f64>().map_err(|e| format!("invalid percentage \"{}\": {}", part, e))?;
+            } else {
+                band.absolute = part.parse::<f64>().map_err(|e| format!("invalid value \"{}\": {}", part, e))?;
+            }
+        }
+
+        if band.absolute < 0.0 || band.percent < 0.0 {
+            return Err(format!("negative deadband \"{}\"", s));
+        }
+
+        Ok(band)
+    }
+}
+
+pub struct Deadband {
+    /* keyed by the last topic element, e.g. current or l1-voltage */
+    bands: HashMap<String, Band>,
+    max_silence: Option<Duration>,
+}
+
+impl Deadband {
+    /* [Deadband] maps metric names to bands, max-silence forces republishing */
+    pub fn from_ini(cfg: &ini::Ini) -> Self {
+        let mut bands = HashMap::new();
+        let mut max_silence = None;
+
+        if let Some(section) = cfg.section(Some("Deadband")) {
+            for (key, value) in section.iter() {
+                if key == "max-silence" {
+                    max_silence = match value.parse::<u64>() {
+                        Ok(0) => None,
+                        Ok(seconds) => Some(Duration::from_secs(seconds)),
+                        Err(e) => {
+                            eprintln!("Failed to parse max-silence in config section [Deadband]: {}", e);
+                            std::process::exit(1);
+                        },
+                    };
+                    continue;
+                }
+
+                match value.parse::<Band>() {
+                    Ok(band) => { bands.insert(key.to_string(), band); },
+                    Err(e) => {
+                        eprintln!("Failed to parse {} in config section [Deadband]: {}", key, e);
+                        std::process::exit(1);
+                    },
+                }
+            }
+        }
+
+        Deadband { bands, max_silence }
+    }
+
+    /* non-numeric payloads and metrics without band are significant on any change */
+    pub fn is_significant(self: &Self, topic: &str, old: &str, new: &str) -> bool {
+        if old == new {
+            return false;
+        }
+
+        let metric = topic.rsplit('/').next().unwrap_or(topic);
+        let band = match self.bands.get(metric) {
+            Some(band) => band,
+            None => return true,
+        };
+
+        match (old.parse::<f64>(), new.parse::<f64>()) {
+            (Ok(old), Ok(new)) => (new - old).abs() > band.absolute.max(old.abs() * band.percent / 100.0),
+            _ => true,
+        }
+    }
+
+    /* value has not been published for longer than the maximum silence period */
+    pub fn is_silent(self: &Self, last_published: Option<&Instant>) -> bool {
+        match (self.max_silence, last_published) {
+            (Some(max_silence), Some(last_published)) => last_published.elapsed() >= max_silence,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deadband(config: &str) -> Deadband {
+        Deadband::from_ini(&ini::Ini::load_from_str(config).unwrap())
+    }
+
+    #[test]
+    fn absolute_boundary() {
+        let d = deadband("[Deadband]\ncurrent = 50\n");
+        assert!(!d.is_significant("/pdu-1/current", "1000", "1050"));
+        assert!(!d.is_significant("/pdu-1/current", "1000", "950"));
+        assert!(d.is_significant("/pdu-1/current", "1000", "1051"));
+        assert!(d.is_significant("/pdu-1/current", "1000", "949"));
+    }
+
+    #[test]
+    fn larger_band_wins() {
+        let d = deadband("[Deadband]\ncurrent = 10 2%\n");
+        assert!(!d.is_significant("/pdu-1/current", "1000", "1020"));
+        assert!(d.is_significant("/pdu-1/current", "1000", "1021"));
+        assert!(!d.is_significant("/pdu-1/current", "100", "110"));
+        assert!(d.is_significant("/pdu-1/current", "100", "111"));
+    }
+
+    #[test]
+    fn unfiltered_values() {
+        let d = deadband("[Deadband]\ncurrent = 50\n");
+        assert!(!d.is_significant("/pdu-1/current", "1000", "1000"));
+        assert!(d.is_significant("/pdu-1/current", "1000 mA", "1010 mA"));
+        assert!(d.is_significant("/pdu-1/voltage", "230", "231"));
+    }
+
+    #[test]
+    fn band_parsing() {
+        assert!("50 2%".parse::<Band>().is_ok());
+        assert!("-1".parse::<Band>().is_err());
+        assert!("2 %".parse::<Band>().is_err());
+        assert!("x%".parse::<Band>().is_err());
+    }
+
+    #[test]
+    fn max_silence() {
+        let d = deadband("[Deadband]\nmax-silence = 60\n");
+        assert!(!d.is_silent(None));
+        assert!(!d.is_silent(Some(&Instant::now())));
+        assert!(d.is_silent(Some(&(Instant::now() - Duration::from_secs(61)))));
+        assert!(!deadband("").is_silent(Some(&(Instant::now() - Duration::from_secs(61)))));
+    }
+}
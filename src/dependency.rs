@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+type Receptacle = (u8, u8, u8);
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum DisableMode {
+    /* refuse disabling a receptacle while receptacles depending on it are on */
+    Refuse,
+    /* disable the dependent receptacles first */
+    Cascade,
+}
+
+impl std::str::FromStr for DisableMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "refuse" => Ok(DisableMode::Refuse),
+            "cascade" => Ok(DisableMode::Cascade),
+            _ => Err(format!("unknown mode \"{}\", expected refuse or cascade", s)),
+        }
+    }
+}
+
+/* receptacles that have to be powered before others, e.g. the switch before the servers */
+#[derive(Clone,Debug)]
+pub struct Dependencies {
+    requires: HashMap<Receptacle, Vec<Receptacle>>,
+    pub mode: DisableMode,
+}
+
+/* all receptacles reachable from r */
+fn visit(edges: &HashMap<Receptacle, Vec<Receptacle>>, r: Receptacle, result: &mut Vec<Receptacle>) -> () {
+    for next in edges.get(&r).map(|e| e.as_slice()).unwrap_or(&[]) {
+        if !result.contains(next) {
+            result.push(*next);
+            visit(edges, *next, result);
+        }
+    }
+}
+
+/* reachable receptacles without r in post-order, i.e. each one after those it leads to */
+fn ordered(edges: &HashMap<Receptacle, Vec<Receptacle>>, r: Receptacle) -> Vec<Receptacle> {
+    let mut order = Vec::new();
+    let mut done = Vec::new();
+    post_order(edges, r, &mut done, &mut order);
+    order.retain(|x| *x != r);
+    order
+}
+
+fn post_order(edges: &HashMap<Receptacle, Vec<Receptacle>>, r: Receptacle, done: &mut Vec<Receptacle>, order: &mut Vec<Receptacle>) -> () {
+    done.push(r);
+    for next in edges.get(&r).map(|e| e.as_slice()).unwrap_or(&[]) {
+        if !done.contains(next) {
+            post_order(edges, *next, done, order);
+        }
+    }
+    order.push(r);
+}
+
+impl Dependencies {
+    /* fails on circular dependencies */
+    pub fn new(requires: HashMap<Receptacle, Vec<Receptacle>>, mode: DisableMode) -> Result<Self, String> {
+        for r in requires.keys() {
+            let mut reachable = Vec::new();
+            visit(&requires, *r, &mut reachable);
+            if reachable.contains(r) {
+                return Err(format!("circular dependency of receptacle {}.{}.{}", r.0, r.1, r.2));
+            }
+        }
+
+        Ok(Dependencies { requires, mode })
+    }
+
+    /* direct and indirect prerequisites, in the order they have to be enabled */
+    pub fn prerequisites(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> Vec<Receptacle> {
+        ordered(&self.requires, (pdu, branch, receptacle))
+    }
+
+    /* direct and indirect dependents, in the order they have to be disabled */
+    pub fn dependents(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> Vec<Receptacle> {
+        let mut required_by : HashMap<Receptacle, Vec<Receptacle>> = HashMap::new();
+        for (r, prerequisites) in &self.requires {
+            for p in prerequisites {
+                required_by.entry(*p).or_default().push(*r);
+            }
+        }
+
+        ordered(&required_by, (pdu, branch, receptacle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies(requires: &[(Receptacle, &[Receptacle])]) -> Result<Dependencies, String> {
+        let requires = requires.iter().map(|(r, p)| (*r, p.to_vec())).collect();
+        Dependencies::new(requires, DisableMode::Cascade)
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        assert!(dependencies(&[((1, 1, 1), &[(1, 1, 1)])]).is_err());
+        assert!(dependencies(&[((1, 1, 1), &[(1, 1, 2)]), ((1, 1, 2), &[(1, 1, 3)]), ((1, 1, 3), &[(1, 1, 1)])]).is_err());
+        assert!(dependencies(&[((1, 1, 3), &[(1, 1, 1), (1, 1, 2)]), ((1, 1, 2), &[(1, 1, 1)])]).is_ok());
+    }
+
+    #[test]
+    fn enable_order() {
+        let d = dependencies(&[((1, 1, 4), &[(1, 1, 2)]), ((1, 1, 2), &[(1, 1, 1)])]).unwrap();
+        assert_eq!(d.prerequisites(1, 1, 4), vec![(1, 1, 1), (1, 1, 2)]);
+        assert_eq!(d.prerequisites(1, 1, 1), vec![]);
+    }
+
+    #[test]
+    fn disable_order() {
+        let d = dependencies(&[((1, 1, 4), &[(1, 1, 2)]), ((1, 1, 2), &[(1, 1, 1)]), ((1, 1, 3), &[(1, 1, 1)])]).unwrap();
+        let dependents = d.dependents(1, 1, 1);
+        assert_eq!(dependents.len(), 3);
+        let position = |r: Receptacle| dependents.iter().position(|x| *x == r).unwrap();
+        assert!(position((1, 1, 4)) < position((1, 1, 2)));
+        assert_eq!(d.dependents(1, 1, 4), vec![]);
+    }
+}
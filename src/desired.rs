@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type Receptacle = (u8, u8, u8);
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum DriftAction {
+    /* only report receptacles that differ from the desired state */
+    Alert,
+    /* report and switch them back to the desired state */
+    Reconcile,
+}
+
+impl std::str::FromStr for DriftAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alert" => Ok(DriftAction::Alert),
+            "reconcile" => Ok(DriftAction::Reconcile),
+            _ => Err(format!("unknown action \"{}\", expected alert or reconcile", s)),
+        }
+    }
+}
+
+impl DriftAction {
+    pub fn describe(self: &Self) -> String {
+        match self {
+            DriftAction::Alert => "alert".to_string(),
+            DriftAction::Reconcile => "reconcile".to_string(),
+        }
+    }
+}
+
+/* power state per receptacle as last requested via control commands, kept across restarts */
+pub struct DesiredState {
+    file: Option<String>,
+    pub action: DriftAction,
+    states: Mutex<BTreeMap<String, bool>>,
+    /* receptacles with an intended temporary difference, e.g. during a power-cycle */
+    suspended: Mutex<HashMap<Receptacle, Instant>>,
+    /* receptacles whose drift has already been reported */
+    drifted: Mutex<HashSet<Receptacle>>,
+}
+
+fn key(pdu: u8, branch: u8, receptacle: u8) -> String {
+    format!("{}.{}.{}", pdu, branch, receptacle)
+}
+
+impl DesiredState {
+    /* the file contains e.g. {"1.1.3": "off"}, a missing file is an empty state */
+    pub fn load(file: Option<String>, action: DriftAction) -> Self {
+        let mut states = BTreeMap::new();
+
+        if let Some(file) = &file {
+            match std::fs::read_to_string(file) {
+                Ok(content) => {
+                    let stored : BTreeMap<String, String> = serde_json::from_str(&content).unwrap_or_else(|e| {
+                        eprintln!("Failed to parse desired state file \"{}\": {}", file, e);
+                        std::process::exit(1);
+                    });
+                    for (receptacle, state) in stored {
+                        states.insert(receptacle, state == "on");
+                    }
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                Err(e) => {
+                    eprintln!("Failed to read desired state file \"{}\": {}", file, e);
+                    std::process::exit(1);
+                },
+            }
+        }
+
+        DesiredState {
+            file,
+            action,
+            states: Mutex::new(states),
+            suspended: Mutex::new(HashMap::new()),
+            drifted: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn is_enabled(self: &Self) -> bool {
+        self.file.is_some()
+    }
+
+    /* written to a temporary file first, so a crash can not leave a truncated file behind */
+    fn save(self: &Self, states: &BTreeMap<String, bool>) -> Result<(), String> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let stored : BTreeMap<&String, &str> = states.iter().map(|(r, on)| (r, if *on { "on" } else { "off" })).collect();
+        let content = serde_json::to_string_pretty(&stored).unwrap_or_default();
+        let tmp = format!("{}.tmp", file);
+
+        std::fs::write(&tmp, content).and_then(|_| std::fs::rename(&tmp, file)).map_err(|e| {
+            eprintln!("Failed to write desired state file \"{}\": {}", file, e);
+            format!("failed to store desired state: {}", e)
+        })
+    }
+
+    /* the state is kept in memory even if it could not be stored */
+    pub fn set(self: &Self, pdu: u8, branch: u8, receptacle: u8, on: bool) -> Result<(), String> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        self.suspended.lock().unwrap().remove(&(pdu, branch, receptacle));
+
+        let mut states = self.states.lock().unwrap();
+        if states.insert(key(pdu, branch, receptacle), on) != Some(on) {
+            return self.save(&states);
+        }
+
+        Ok(())
+    }
+
+    pub fn get(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> Option<bool> {
+        self.states.lock().unwrap().get(&key(pdu, branch, receptacle)).copied()
+    }
+
+    pub fn suspend(self: &Self, pdu: u8, branch: u8, receptacle: u8, duration: Duration) -> () {
+        self.suspended.lock().unwrap().insert((pdu, branch, receptacle), Instant::now() + duration);
+    }
+
+    fn is_suspended(self: &Self, id: Receptacle) -> bool {
+        let mut suspended = self.suspended.lock().unwrap();
+        match suspended.get(&id) {
+            Some(until) if Instant::now() < *until => true,
+            Some(_) => {
+                suspended.remove(&id);
+                false
+            },
+            None => false,
+        }
+    }
+
+    /* desired state if the actual one differs from it, each drift is only reported once */
+    pub fn check(self: &Self, pdu: u8, branch: u8, receptacle: u8, actual: bool) -> Option<(bool, bool)> {
+        let id = (pdu, branch, receptacle);
+        let desired = self.get(pdu, branch, receptacle)?;
+        let mut drifted = self.drifted.lock().unwrap();
+
+        if desired == actual {
+            drifted.remove(&id);
+            return None;
+        }
+
+        if self.is_suspended(id) {
+            return None;
+        }
+
+        let is_new = drifted.insert(id);
+        Some((desired, is_new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("pdu-ctrl-test-{}-{}.json", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn persists() {
+        let path = file("persists");
+        let state = DesiredState::load(Some(path.clone()), DriftAction::Alert);
+        assert_eq!(state.set(1, 1, 3, false), Ok(()));
+        assert_eq!(state.set(1, 1, 4, true), Ok(()));
+
+        let state = DesiredState::load(Some(path.clone()), DriftAction::Alert);
+        assert_eq!(state.get(1, 1, 3), Some(false));
+        assert_eq!(state.get(1, 1, 4), Some(true));
+        assert_eq!(state.get(1, 1, 5), None);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn failed_write() {
+        let state = DesiredState::load(Some("/nonexistent/pdu-ctrl/desired-state.json".to_string()), DriftAction::Alert);
+        assert!(state.set(1, 1, 3, false).is_err());
+        assert_eq!(state.get(1, 1, 3), Some(false));
+    }
+
+    #[test]
+    fn drift_reported_once() {
+        let state = DesiredState::load(Some(file("drift")), DriftAction::Alert);
+        state.states.lock().unwrap().insert(key(1, 1, 3), true);
+        assert_eq!(state.check(1, 1, 3, true), None);
+        assert_eq!(state.check(1, 1, 3, false), Some((true, true)));
+        assert_eq!(state.check(1, 1, 3, false), Some((true, false)));
+        assert_eq!(state.check(1, 1, 3, true), None);
+        assert_eq!(state.check(1, 1, 3, false), Some((true, true)));
+        assert_eq!(state.check(1, 1, 4, false), None);
+    }
+
+    #[test]
+    fn suspended() {
+        let state = DesiredState::load(Some(file("suspended")), DriftAction::Alert);
+        state.states.lock().unwrap().insert(key(1, 1, 3), true);
+        state.suspend(1, 1, 3, Duration::from_secs(60));
+        assert_eq!(state.check(1, 1, 3, false), None);
+        state.suspend(1, 1, 3, Duration::from_secs(0));
+        assert_eq!(state.check(1, 1, 3, false), Some((true, true)));
+    }
+
+    #[test]
+    fn disabled_without_file() {
+        let state = DesiredState::load(None, DriftAction::Alert);
+        assert_eq!(state.set(1, 1, 3, false), Ok(()));
+        assert_eq!(state.check(1, 1, 3, true), None);
+    }
+}
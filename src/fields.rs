@@ -0,0 +1,235 @@
+use liebert_mpx::{PDUInfo, BranchInfo, ReceptacleInfo};
+
+/* a field as read from the PDU, numbers are given in their unit */
+pub enum Value {
+    Number(f32),
+    Text(String),
+}
+
+/* topic below the PDU, branch or receptacle, accessor, factor from the unit to the scaled integer format, unit,
+ * Home Assistant name and metric name of values exported as sensors;
+ * the per-field topics, the JSON document, the unit topics, discovery and metrics are all generated from this */
+pub type FieldDesc<T> = (&'static str, fn(&T) -> Value, f32, &'static str, Option<(&'static str, &'static str)>);
+
+/* topic, Home Assistant name, metric name, factor to the scaled integer format, unit */
+pub type SensorDesc = (&'static str, &'static str, &'static str, f32, &'static str);
+
+pub trait Fields: Sized + 'static {
+    const FIELDS: &'static [FieldDesc<Self>];
+}
+
+fn on_off(value: bool) -> Value {
+    Value::Text(if value { "on" } else { "off" }.to_string())
+}
+
+const PDU_FIELDS: &[FieldDesc<PDUInfo>] = &[
+    ("status/accumulated-energy", |i| Value::Number(i.status.accumulated_energy), 1000.0, "kWh", Some(("Energy", "energy"))),
+    ("status/input-power", |i| Value::Number(i.status.input_power), 1000.0, "W", Some(("Input Power", "power"))),
+    ("status/l1-voltage", |i| Value::Number(i.status.voltage_l1_n), 1000.0, "V", Some(("L1 Voltage", "voltage"))),
+    ("status/l2-voltage", |i| Value::Number(i.status.voltage_l2_n), 1000.0, "V", Some(("L2 Voltage", "voltage"))),
+    ("status/l3-voltage", |i| Value::Number(i.status.voltage_l3_n), 1000.0, "V", Some(("L3 Voltage", "voltage"))),
+    ("status/l1-current", |i| Value::Number(i.status.current_l1), 1000.0, "A", Some(("L1 Current", "current"))),
+    ("status/l2-current", |i| Value::Number(i.status.current_l2), 1000.0, "A", Some(("L2 Current", "current"))),
+    ("status/l3-current", |i| Value::Number(i.status.current_l3), 1000.0, "A", Some(("L3 Current", "current"))),
+    ("status/n-current", |i| Value::Number(i.status.current_n), 1000.0, "A", Some(("N Current", "current"))),
+    ("status/l1-current-available-to-alarm", |i| Value::Number(i.status.current_available_to_alarm_l1), 1000.0, "A", None),
+    ("status/l2-current-available-to-alarm", |i| Value::Number(i.status.current_available_to_alarm_l2), 1000.0, "A", None),
+    ("status/l3-current-available-to-alarm", |i| Value::Number(i.status.current_available_to_alarm_l3), 1000.0, "A", None),
+    ("status/l1-current-utilization", |i| Value::Number(i.status.current_utilization_l1), 10.0, "%", Some(("L1 Current Utilization", "current_utilization"))),
+    ("status/l2-current-utilization", |i| Value::Number(i.status.current_utilization_l2), 10.0, "%", Some(("L2 Current Utilization", "current_utilization"))),
+    ("status/l3-current-utilization", |i| Value::Number(i.status.current_utilization_l3), 10.0, "%", Some(("L3 Current Utilization", "current_utilization"))),
+    ("status/line-frequency", |i| Value::Number(i.status.line_frequency), 10.0, "Hz", Some(("Line Frequency", "frequency"))),
+
+    ("events/l1-low-voltage", |i| Value::Text(format!("{:?}", i.events.low_voltage_l1)), 1.0, "", None),
+    ("events/l2-low-voltage", |i| Value::Text(format!("{:?}", i.events.low_voltage_l2)), 1.0, "", None),
+    ("events/l3-low-voltage", |i| Value::Text(format!("{:?}", i.events.low_voltage_l3)), 1.0, "", None),
+    ("events/l1-over-current", |i| Value::Text(format!("{:?}", i.events.over_current_l1)), 1.0, "", None),
+    ("events/l2-over-current", |i| Value::Text(format!("{:?}", i.events.over_current_l2)), 1.0, "", None),
+    ("events/l3-over-current", |i| Value::Text(format!("{:?}", i.events.over_current_l3)), 1.0, "", None),
+    ("events/n-over-current", |i| Value::Text(format!("{:?}", i.events.over_current_n)), 1.0, "", None),
+    ("events/l1-low-current", |i| Value::Text(format!("{:?}", i.events.low_current_l1)), 1.0, "", None),
+    ("events/l2-low-current", |i| Value::Text(format!("{:?}", i.events.low_current_l2)), 1.0, "", None),
+    ("events/l3-low-current", |i| Value::Text(format!("{:?}", i.events.low_current_l3)), 1.0, "", None),
+    ("events/failure", |i| Value::Text(format!("{:?}", i.events.failure)), 1.0, "", None),
+    ("events/communication-fail", |i| Value::Text(format!("{:?}", i.events.communication_fail)), 1.0, "", None),
+
+    ("settings/label", |i| Value::Text(i.settings.label.to_string()), 1.0, "", None),
+    ("settings/asset-tag-1", |i| Value::Text(i.settings.asset_tag_1.to_string()), 1.0, "", None),
+    ("settings/asset-tag-2", |i| Value::Text(i.settings.asset_tag_2.to_string()), 1.0, "", None),
+    ("settings/n-over-current-alarm-threshold", |i| Value::Number(i.settings.n_over_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/n-over-current-warning-threshold", |i| Value::Number(i.settings.n_over_current_warning_threshold as f32), 1.0, "%", None),
+    ("settings/l1-low-current-alarm-threshold", |i| Value::Number(i.settings.l1_low_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/l1-over-current-alarm-threshold", |i| Value::Number(i.settings.l1_over_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/l1-over-current-warning-threshold", |i| Value::Number(i.settings.l1_over_current_warning_threshold as f32), 1.0, "%", None),
+    ("settings/l2-low-current-alarm-threshold", |i| Value::Number(i.settings.l2_low_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/l2-over-current-alarm-threshold", |i| Value::Number(i.settings.l2_over_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/l2-over-current-warning-threshold", |i| Value::Number(i.settings.l2_over_current_warning_threshold as f32), 1.0, "%", None),
+    ("settings/l3-low-current-alarm-threshold", |i| Value::Number(i.settings.l3_low_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/l3-over-current-alarm-threshold", |i| Value::Number(i.settings.l3_over_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/l3-over-current-warning-threshold", |i| Value::Number(i.settings.l3_over_current_warning_threshold as f32), 1.0, "%", None),
+
+    ("hardware/pem-model", |i| Value::Text(format!("{:?}", i.hardware.pem_model)), 1.0, "", None),
+    ("hardware/fw-version", |i| Value::Text(i.hardware.fw_version.to_string()), 1.0, "", None),
+    ("hardware/serial-number", |i| Value::Text(i.hardware.serial_number.to_string()), 1.0, "", None),
+    ("hardware/wiring-type", |i| Value::Text(i.hardware.wiring_type.to_string()), 1.0, "", None),
+    ("hardware/rated-input-voltage", |i| Value::Number(i.hardware.rated_input_voltage as f32), 1000.0, "V", None),
+    ("hardware/rated-input-current", |i| Value::Number(i.hardware.rated_input_current as f32), 1000.0, "A", None),
+    ("hardware/rated-input-line-frequency", |i| Value::Number(i.hardware.rated_input_line_frequency as f32), 10.0, "Hz", None),
+];
+
+const BRANCH_FIELDS: &[FieldDesc<BranchInfo>] = &[
+    ("status/accumulated-energy", |i| Value::Number(i.status.accumulated_energy), 1000.0, "kWh", Some(("Energy", "energy"))),
+    ("status/voltage", |i| Value::Number(i.status.voltage), 1000.0, "V", Some(("Voltage", "voltage"))),
+    ("status/current", |i| Value::Number(i.status.current), 1000.0, "A", Some(("Current", "current"))),
+    ("status/current-available-to-alarm", |i| Value::Number(i.status.current_available_to_alarm), 1000.0, "A", None),
+    ("status/current-utilization", |i| Value::Number(i.status.current_utilization), 10.0, "%", Some(("Current Utilization", "current_utilization"))),
+    ("status/power", |i| Value::Number(i.status.power), 1000.0, "W", Some(("Power", "power"))),
+    ("status/apparent-power", |i| Value::Number(i.status.apparent_power), 1000.0, "VA", Some(("Apparent Power", "apparent_power"))),
+    ("status/power-factor", |i| Value::Number(i.status.power_factor), 1.0, "", None),
+
+    ("events/low-voltage", |i| Value::Text(format!("{:?}", i.events.low_voltage)), 1.0, "", None),
+    ("events/over-current", |i| Value::Text(format!("{:?}", i.events.over_current)), 1.0, "", None),
+    ("events/low-current", |i| Value::Text(format!("{:?}", i.events.low_current)), 1.0, "", None),
+    ("events/failure", |i| Value::Text(format!("{:?}", i.events.failure)), 1.0, "", None),
+    ("events/breaker-open", |i| Value::Text(format!("{:?}", i.events.breaker_open)), 1.0, "", None),
+
+    ("settings/label", |i| Value::Text(i.settings.label.to_string()), 1.0, "", None),
+    ("settings/asset-tag-1", |i| Value::Text(i.settings.asset_tag_1.to_string()), 1.0, "", None),
+    ("settings/asset-tag-2", |i| Value::Text(i.settings.asset_tag_2.to_string()), 1.0, "", None),
+    ("settings/over-current-alarm-threshold", |i| Value::Number(i.settings.over_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/over-current-warning-threshold", |i| Value::Number(i.settings.over_current_warning_threshold as f32), 1.0, "%", None),
+    ("settings/low-current-alarm-threshold", |i| Value::Number(i.settings.low_current_alarm_threshold as f32), 1.0, "%", None),
+
+    ("hardware/brm-model", |i| Value::Text(format!("{:?}", i.hardware.brm_model)), 1.0, "", None),
+    ("hardware/fw-version", |i| Value::Text(i.hardware.fw_version.to_string()), 1.0, "", None),
+    ("hardware/serial-number", |i| Value::Text(i.hardware.serial_number.to_string()), 1.0, "", None),
+    ("hardware/receptacle-type", |i| Value::Text(i.hardware.receptacle_type.to_string()), 1.0, "", None),
+    ("hardware/capabilities", |i| Value::Text(i.hardware.capabilities.to_string()), 1.0, "", None),
+    ("hardware/line-source", |i| Value::Text(i.hardware.line_source.to_string()), 1.0, "", None),
+    ("hardware/rated-line-voltage", |i| Value::Number(i.hardware.rated_line_voltage as f32), 1000.0, "V", None),
+    ("hardware/rated-line-current", |i| Value::Number(i.hardware.rated_line_current as f32), 1000.0, "A", None),
+    ("hardware/rated-line-frequency", |i| Value::Number(i.hardware.rated_line_frequency as f32), 10.0, "Hz", None),
+];
+
+const RECEPTACLE_FIELDS: &[FieldDesc<ReceptacleInfo>] = &[
+    ("status/accumulated-energy", |i| Value::Number(i.status.accumulated_energy), 1000.0, "kWh", Some(("Energy", "energy"))),
+    ("status/voltage", |i| Value::Number(i.status.voltage), 1000.0, "V", Some(("Voltage", "voltage"))),
+    ("status/current", |i| Value::Number(i.status.current), 1000.0, "A", Some(("Current", "current"))),
+    ("status/current-available-to-alarm", |i| Value::Number(i.status.current_available_to_alarm), 1000.0, "A", None),
+    ("status/current-utilization", |i| Value::Number(i.status.current_utilization), 10.0, "%", Some(("Current Utilization", "current_utilization"))),
+    ("status/power", |i| Value::Number(i.status.power), 1000.0, "W", Some(("Power", "power"))),
+    ("status/apparent-power", |i| Value::Number(i.status.apparent_power), 1000.0, "VA", Some(("Apparent Power", "apparent_power"))),
+    ("status/power-factor", |i| Value::Number(i.status.power_factor), 1.0, "", None),
+    ("status/current-crest-factor", |i| Value::Number(i.status.current_crest_factor), 1.0, "", None),
+
+    ("events/over-current", |i| Value::Text(format!("{:?}", i.events.over_current)), 1.0, "", None),
+    ("events/low-current", |i| Value::Text(format!("{:?}", i.events.low_current)), 1.0, "", None),
+
+    ("settings/label", |i| Value::Text(i.settings.label.to_string()), 1.0, "", None),
+    ("settings/asset-tag-1", |i| Value::Text(i.settings.asset_tag_1.to_string()), 1.0, "", None),
+    ("settings/asset-tag-2", |i| Value::Text(i.settings.asset_tag_2.to_string()), 1.0, "", None),
+    ("settings/over-current-alarm-threshold", |i| Value::Number(i.settings.over_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/over-current-warning-threshold", |i| Value::Number(i.settings.over_current_warning_threshold as f32), 1.0, "%", None),
+    ("settings/low-current-alarm-threshold", |i| Value::Number(i.settings.low_current_alarm_threshold as f32), 1.0, "%", None),
+    ("settings/power-state", |i| on_off(i.settings.power_state), 1.0, "", None),
+    ("settings/power-control", |i| on_off(i.settings.power_control), 1.0, "", None),
+    ("settings/power-control-locked", |i| on_off(i.settings.control_lock_state), 1.0, "", None),
+    ("settings/power-on-delay", |i| Value::Number(i.settings.power_on_delay as f32), 1.0, "s", None),
+
+    ("hardware/receptacle-type", |i| Value::Text(i.hardware.receptacle_type.to_string()), 1.0, "", None),
+    ("hardware/line-source", |i| Value::Text(i.hardware.line_source.to_string()), 1.0, "", None),
+    ("hardware/capabilities", |i| Value::Text(i.hardware.capabilities.to_string()), 1.0, "", None),
+];
+
+impl Fields for PDUInfo {
+    const FIELDS: &'static [FieldDesc<Self>] = PDU_FIELDS;
+}
+
+impl Fields for BranchInfo {
+    const FIELDS: &'static [FieldDesc<Self>] = BRANCH_FIELDS;
+}
+
+impl Fields for ReceptacleInfo {
+    const FIELDS: &'static [FieldDesc<Self>] = RECEPTACLE_FIELDS;
+}
+
+/* fields exported to Home Assistant and Prometheus */
+pub fn sensors<T: Fields>() -> Vec<SensorDesc> {
+    T::FIELDS.iter().filter_map(|(topic, _, scale, unit, export)| export.map(|(name, metric)| (*topic, name, metric, *scale, *unit))).collect()
+}
+
+/* sensor of a published topic, level is pdu, branch or receptacle, e.g. ("branch", "status/current") */
+pub fn sensor(level: &str, topic: &str) -> Option<SensorDesc> {
+    let sensors = match level {
+        "pdu" => sensors::<PDUInfo>(),
+        "branch" => sensors::<BranchInfo>(),
+        "receptacle" => sensors::<ReceptacleInfo>(),
+        _ => return None,
+    };
+
+    sensors.into_iter().find(|s| s.0 == topic)
+}
+
+/* Home Assistant device class of a sensor's unit */
+pub fn device_class(unit: &str) -> Option<&'static str> {
+    match unit {
+        "kWh" => Some("energy"),
+        "W" => Some("power"),
+        "VA" => Some("apparent_power"),
+        "V" => Some("voltage"),
+        "A" => Some("current"),
+        "Hz" => Some("frequency"),
+        _ => None,
+    }
+}
+
+/* Prometheus base unit, e.g. volts */
+pub fn unit_name(unit: &str) -> &'static str {
+    match unit {
+        "kWh" => "kilowatt_hours",
+        "W" => "watts",
+        "VA" => "voltamperes",
+        "V" => "volts",
+        "A" => "amperes",
+        "Hz" => "hertz",
+        "%" => "percent",
+        "s" => "seconds",
+        _ => "",
+    }
+}
+
+/* energy only ever increases, everything else is a momentary reading */
+pub fn is_counter(unit: &str) -> bool {
+    unit == "kWh"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unique<T: Fields>() -> bool {
+        T::FIELDS.iter().enumerate().all(|(i, f)| T::FIELDS[..i].iter().all(|g| g.0 != f.0))
+    }
+
+    #[test]
+    fn topics() {
+        assert!(unique::<PDUInfo>() && unique::<BranchInfo>() && unique::<ReceptacleInfo>());
+        for topic in PDU_FIELDS.iter().map(|f| f.0).chain(BRANCH_FIELDS.iter().map(|f| f.0)).chain(RECEPTACLE_FIELDS.iter().map(|f| f.0)) {
+            let group = topic.split('/').next().unwrap_or("");
+            assert!(["status", "events", "settings", "hardware"].contains(&group), "{}", topic);
+        }
+    }
+
+    #[test]
+    fn exported_sensors() {
+        assert_eq!(sensor("pdu", "status/l2-voltage"), Some(("status/l2-voltage", "L2 Voltage", "voltage", 1000.0, "V")));
+        assert_eq!(sensor("receptacle", "status/current-utilization").map(|s| s.3), Some(10.0));
+        assert_eq!(sensor("branch", "status/current-available-to-alarm"), None);
+        assert_eq!(sensor("branch", "status/line-frequency"), None);
+        assert_eq!(sensor("group", "status/current"), None);
+
+        for (_, _, _, _, unit) in sensors::<PDUInfo>().into_iter().chain(sensors::<ReceptacleInfo>()) {
+            assert!(!unit_name(unit).is_empty(), "{}", unit);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::MQTTMsgList;
+use crate::MQTTMsg;
+use crate::mqttify::{StateFormat, Units};
+use crate::fields::{self, SensorDesc};
+use serde_json::json;
+
+pub struct Discovery<'a> {
+    discovery_prefix: &'a str,
+    node_id: String,
+    prefix: &'a str,
+    state_prefix: String,
+    name: &'a str,
+    units: Units,
+    state_format: StateFormat,
+}
+
+impl<'a> Discovery<'a> {
+    /* name is the PDU's config section name, empty for a single [PDU] section */
+    pub fn new(discovery_prefix: &'a str, clientname: &str, prefix: &'a str, name: &'a str, units: Units, state_format: StateFormat) -> Self {
+        let node_id = if name.is_empty() { clientname.to_string() } else { format!("{}-{}", clientname, name) };
+        /* Home Assistant only accepts [a-zA-Z0-9_-] for node and object IDs */
+        let node_id = node_id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+        let state_prefix = if name.is_empty() { prefix.to_string() } else { format!("{}/{}", prefix, name) };
+        Discovery { discovery_prefix, node_id, prefix, state_prefix, name, units, state_format }
+    }
+
+    fn device_name(self: &Self, name: String) -> String {
+        if self.name.is_empty() {
+            name
+        } else {
+            format!("{} {}", self.name, name)
+        }
+    }
+
+    fn pdu_device(self: &Self, pdu: u8) -> serde_json::Value {
+        json!({
+            "identifiers": [format!("{}-pdu-{}", self.node_id, pdu)],
+            "name": self.device_name(format!("PDU {}", pdu)),
+            "manufacturer": "Vertiv",
+            "model": "Liebert MPX",
+        })
+    }
+
+    fn branch_device(self: &Self, pdu: u8, branch: u8) -> serde_json::Value {
+        json!({
+            "identifiers": [format!("{}-pdu-{}-branch-{}", self.node_id, pdu, branch)],
+            "name": self.device_name(format!("PDU {} Branch {}", pdu, branch)),
+            "manufacturer": "Vertiv",
+            "model": "Liebert MPX BRM",
+            "via_device": format!("{}-pdu-{}", self.node_id, pdu),
+        })
+    }
+
+    fn availability(self: &Self, pdu: u8) -> serde_json::Value {
+        json!([
+            { "topic": format!("{}/availability", self.prefix) },
+            { "topic": format!("{}/pdu-{}/availability", self.state_prefix, pdu) },
+        ])
+    }
+
+    /* state topic and template expression for a field, e.g. status/current */
+    fn state(self: &Self, path: &str, topic: &str) -> (String, String) {
+        if self.state_format.fields() {
+            return (format!("{}{}/{}", self.state_prefix, path, topic), "value".to_string());
+        }
+
+        let (group, leaf) = topic.split_once('/').unwrap_or(("", topic));
+        (format!("{}{}/state", self.state_prefix, path), format!("value_json['{}']['{}']", group, leaf))
+    }
+
+    fn message(self: &Self, component: &str, object_id: &str, config: serde_json::Value) -> MQTTMsg {
+        MQTTMsg {
+            topic: format!("{}/{}/{}/{}/config", self.discovery_prefix, component, self.node_id, object_id),
+            payload: config.to_string(),
+            retained: true,
+        }
+    }
+
+    fn sensors(self: &Self, pdu: u8, path: &str, name: &str, device: &serde_json::Value, sensors: &[SensorDesc]) -> MQTTMsgList {
+        let mut result : MQTTMsgList = Vec::new();
+        let id = path.trim_start_matches('/').replace('/', "-");
+
+        for (topic, sensorname, _, scale, unit) in sensors {
+            let (state_topic, value) = self.state(path, topic);
+            let template = match self.units {
+                Units::Scaled => format!("{{{{ {} | float / {} }}}}", value, scale),
+                Units::SI => format!("{{{{ {} | float }}}}", value),
+            };
+            let object_id = format!("{}-{}", id, topic.rsplit('/').next().unwrap_or(topic));
+            let mut config = json!({
+                "name": format!("{} {}", name, sensorname),
+                "unique_id": format!("{}-{}", self.node_id, object_id),
+                "state_topic": state_topic,
+                "unit_of_measurement": unit,
+                "value_template": template,
+                "state_class": if fields::is_counter(unit) { "total_increasing" } else { "measurement" },
+                "device": device,
+                "availability": self.availability(pdu),
+                "availability_mode": "all",
+            });
+            if let Some(class) = fields::device_class(unit) {
+                config["device_class"] = json!(class);
+            }
+            result.push(self.message("sensor", &object_id, config));
+        }
+
+        result
+    }
+
+    fn switch(self: &Self, pdu: u8, branch: u8, receptacle: u8, device: &serde_json::Value) -> MQTTMsg {
+        let path = format!("/pdu-{}/branch-{}/receptacle-{}", pdu, branch, receptacle);
+        let object_id = format!("pdu-{}-branch-{}-receptacle-{}-power-state", pdu, branch, receptacle);
+        let (state_topic, value) = self.state(&path, "settings/power-state");
+        let mut config = json!({
+            "name": format!("Receptacle {}", receptacle),
+            "unique_id": format!("{}-{}", self.node_id, object_id),
+            "command_topic": format!("{}{}/control", self.state_prefix, path),
+            "state_topic": state_topic,
+            "payload_on": "enable",
+            "payload_off": "disable",
+            "state_on": "on",
+            "state_off": "off",
+            "device_class": "outlet",
+            "device": device,
+            "availability": self.availability(pdu),
+            "availability_mode": "all",
+        });
+        if !self.state_format.fields() {
+            config["value_template"] = json!(format!("{{{{ {} }}}}", value));
+        }
+
+        self.message("switch", &object_id, config)
+    }
+
+    /* discovery configs for all PDUs, branches and receptacles */
+    pub fn config(self: &Self, receptacles: &liebert_mpx::ReceptacleList) -> MQTTMsgList {
+        let mut result : MQTTMsgList = Vec::new();
+        let mut pdus: Vec<u8> = Vec::new();
+        let mut branches: Vec<(u8, u8)> = Vec::new();
+
+        for r in receptacles {
+            if !pdus.contains(&r.pdu) {
+                pdus.push(r.pdu);
+                let device = self.pdu_device(r.pdu);
+                let path = format!("/pdu-{}", r.pdu);
+                result.append(&mut self.sensors(r.pdu, &path, "PDU", &device, &fields::sensors::<liebert_mpx::PDUInfo>()));
+            }
+
+            let device = self.branch_device(r.pdu, r.branch);
+            if !branches.contains(&(r.pdu, r.branch)) {
+                branches.push((r.pdu, r.branch));
+                let path = format!("/pdu-{}/branch-{}", r.pdu, r.branch);
+                result.append(&mut self.sensors(r.pdu, &path, "Branch", &device, &fields::sensors::<liebert_mpx::BranchInfo>()));
+            }
+
+            let path = format!("/pdu-{}/branch-{}/receptacle-{}", r.pdu, r.branch, r.receptacle);
+            let name = format!("Receptacle {}", r.receptacle);
+            result.append(&mut self.sensors(r.pdu, &path, &name, &device, &fields::sensors::<liebert_mpx::ReceptacleInfo>()));
+            result.push(self.switch(r.pdu, r.branch, r.receptacle, &device));
+        }
+
+        result
+    }
+}
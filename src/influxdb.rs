@@ -0,0 +1,217 @@
+use crate::MQTTMsgList;
+use crate::metrics;
+use crate::mqttify::Units;
+use crate::sink::Sink;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/* give up on a write instead of delaying the PDU's polling */
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone,Debug)]
+pub enum Target {
+    /* host:port and path including the query, e.g. /api/v2/write?org=o&bucket=b */
+    Http(String, String),
+    /* host:port */
+    Udp(String),
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    /* http://host:port/path?query or udp://host:port */
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        if url.starts_with("https://") {
+            return Err(format!("unsupported URL \"{}\", https is not supported, use http:// or udp://", url));
+        }
+
+        if let Some(rest) = url.strip_prefix("http://") {
+            let (host, path) = match rest.find('/') {
+                Some(i) => (&rest[..i], &rest[i..]),
+                None => (rest, "/write"),
+            };
+            if host.is_empty() {
+                return Err(format!("missing host in \"{}\"", url));
+            }
+            let host = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+            return Ok(Target::Http(host, path.to_string()));
+        }
+
+        if let Some(address) = url.strip_prefix("udp://") {
+            if !address.contains(':') {
+                return Err(format!("missing port in \"{}\"", url));
+            }
+            return Ok(Target::Udp(address.trim_end_matches('/').to_string()));
+        }
+
+        Err(format!("unsupported URL \"{}\", expected http:// or udp://", url))
+    }
+}
+
+#[derive(Clone)]
+pub struct InfluxCfg {
+    pub target: Target,
+    pub token: Option<String>,
+}
+
+/* writes status values of a single PDU as line protocol with float fields,
+ * every poll is written, so the series has no gaps while values do not change */
+pub struct InfluxDB {
+    cfg: InfluxCfg,
+    name: String,
+    units: Units,
+}
+
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+impl InfluxDB {
+    pub fn new(cfg: InfluxCfg, name: &str, units: Units) -> Self {
+        InfluxDB { cfg, name: name.to_string(), units }
+    }
+
+    /* one line per PDU, branch and receptacle, e.g. receptacle,pdu=1,branch=1,receptacle=3 current=1.203,power=230.1 */
+    fn lines(self: &Self, messages: &MQTTMsgList) -> String {
+        let mut series : BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for sample in metrics::samples(messages, self.units) {
+            let mut key = sample.level.to_string();
+            if !self.name.is_empty() {
+                key.push_str(&format!(",name={}", escape_tag(&self.name)));
+            }
+            key.push_str(&format!(",pdu={}", sample.pdu));
+            if !sample.branch.is_empty() {
+                key.push_str(&format!(",branch={}", sample.branch));
+            }
+            if !sample.receptacle.is_empty() {
+                key.push_str(&format!(",receptacle={}", sample.receptacle));
+            }
+
+            let field = if sample.line.is_empty() { sample.name().to_string() } else { format!("{}_{}", sample.line, sample.name()) };
+            series.entry(key).or_default().push(format!("{}={:?}", field, sample.value));
+        }
+
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+        series.iter().map(|(key, fields)| format!("{} {} {}\n", key, fields.join(","), timestamp)).collect()
+    }
+
+    async fn post(self: &Self, host: &str, path: &str, body: &str) -> Result<(), String> {
+        let mut stream = tokio::net::TcpStream::connect(host).await.map_err(|e| e.to_string())?;
+
+        let mut request = format!("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n", path, host, body.len());
+        if let Some(token) = &self.cfg.token {
+            request.push_str(&format!("Authorization: Token {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        stream.write_all(request.as_bytes()).await.map_err(|e| e.to_string())?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.map_err(|e| e.to_string())?;
+        let response = String::from_utf8_lossy(&response);
+        let status = response.lines().next().unwrap_or("");
+        if !status.split_whitespace().nth(1).map_or(false, |code| code.starts_with('2')) {
+            return Err(format!("unexpected response \"{}\"", status));
+        }
+
+        Ok(())
+    }
+
+    async fn send(self: &Self, body: String) -> Result<(), String> {
+        match &self.cfg.target {
+            Target::Http(host, path) => self.post(host, path, &body).await,
+            Target::Udp(address) => {
+                let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await.map_err(|e| e.to_string())?;
+                socket.send_to(body.as_bytes(), address.as_str()).await.map_err(|e| e.to_string())?;
+                Ok(())
+            },
+        }
+    }
+
+    async fn write_lines(self: &Self, messages: &MQTTMsgList) -> () {
+        let body = self.lines(messages);
+        if body.is_empty() {
+            return;
+        }
+
+        match tokio::time::timeout(WRITE_TIMEOUT, self.send(body)).await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => eprintln!("Failed to write to InfluxDB: {}", e),
+            Err(_) => eprintln!("Timeout while writing to InfluxDB"),
+        }
+    }
+}
+
+impl Sink for InfluxDB {
+    fn write<'a>(self: &'a Self, messages: &'a MQTTMsgList) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(self.write_lines(messages))
+    }
+
+    fn every_sample(self: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MQTTMsg;
+
+    fn msg(topic: &str, payload: &str) -> MQTTMsg {
+        MQTTMsg { topic: topic.to_string(), payload: payload.to_string(), retained: true }
+    }
+
+    #[test]
+    fn targets() {
+        match "http://influxdb:8086/api/v2/write?org=o&bucket=b".parse::<Target>() {
+            Ok(Target::Http(host, path)) => {
+                assert_eq!(host, "influxdb:8086");
+                assert_eq!(path, "/api/v2/write?org=o&bucket=b");
+            },
+            target => panic!("unexpected {:?}", target),
+        }
+        match "http://influxdb".parse::<Target>() {
+            Ok(Target::Http(host, path)) => {
+                assert_eq!(host, "influxdb:80");
+                assert_eq!(path, "/write");
+            },
+            target => panic!("unexpected {:?}", target),
+        }
+        assert!(matches!("udp://influxdb:8089/".parse::<Target>(), Ok(Target::Udp(address)) if address == "influxdb:8089"));
+
+        let e = "https://influxdb:8086/api/v2/write".parse::<Target>().unwrap_err();
+        assert!(e.contains("https is not supported"), "{}", e);
+        assert!("udp://influxdb".parse::<Target>().is_err());
+        assert!("http:///write".parse::<Target>().is_err());
+        assert!("influxdb:8086".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn line_protocol() {
+        let influx = InfluxDB::new(InfluxCfg { target: Target::Udp("localhost:8089".to_string()), token: None }, "rack 3", Units::Scaled);
+        let lines = influx.lines(&vec![
+            msg("/pdu-1/status/l1-voltage", "230100"),
+            msg("/pdu-1/branch-1/receptacle-3/status/current", "1203"),
+            msg("/pdu-1/branch-1/receptacle-3/status/power", "230100"),
+            msg("/pdu-1/branch-1/receptacle-3/settings/label", "switch"),
+            msg("/pdu-1/branch-1/receptacle-3/status/power/unit", "mW"),
+        ]);
+
+        let lines : Vec<(&str, &str)> = lines.lines().map(|l| l.rsplit_once(' ').unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].0, "pdu,name=rack\\ 3,pdu=1 l1_voltage=230.1");
+        assert_eq!(lines[1].0, "receptacle,name=rack\\ 3,pdu=1,branch=1,receptacle=3 current=1.203,power=230.1");
+        assert!(lines[0].1.parse::<u128>().is_ok());
+
+        assert_eq!(influx.lines(&vec![msg("/pdu-1/settings/label", "x")]), "");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_tag("a b,c=d\\e"), "a\\ b\\,c\\=d\\\\e");
+    }
+}
@@ -1,14 +1,29 @@
 extern crate liebert_mpx as liebert;
-use rumqttc::{MqttOptions, AsyncClient, QoS};
+use rumqttc::{MqttOptions, AsyncClient, QoS, LastWill};
 use std::time::{Duration, Instant};
-use std::thread::sleep;
 use std::pin::Pin;
 use std::future::Future;
-use tokio::sync::mpsc;
+use std::convert::{TryFrom, TryInto};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{mpsc, Notify, Semaphore};
 use ini::Ini;
+use serde::{Serialize, Deserialize};
 
 mod mqttify;
 use crate::mqttify::ToMQTT;
+mod fields;
+mod hass;
+mod policy;
+use crate::policy::Action;
+mod deadband;
+mod metrics;
+mod sink;
+mod influxdb;
+mod desired;
+mod protection;
+mod sequence;
+mod budget;
+mod dependency;
 
 #[derive(Copy,Clone,PartialEq)]
 enum TaskPriority {
@@ -24,6 +39,102 @@ pub struct MQTTMsg {
 }
 pub type MQTTMsgList = Vec<MQTTMsg>;
 
+/* number of consecutive failed requests before a PDU is reported offline */
+const PDU_OFFLINE_THRESHOLD: u32 = 3;
+
+/* retry delay for failed tasks, doubled on every failure */
+const RETRY_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/* delay between MQTT reconnection attempts, doubled on every failure */
+const MQTT_RECONNECT_MIN: Duration = Duration::from_secs(1);
+const MQTT_RECONNECT_MAX: Duration = Duration::from_secs(60);
+
+/* give up on a publish request instead of blocking the scheduler */
+const MQTT_PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/* systemd watchdog notification interval, skipped once all PDU schedulers are stuck */
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(30);
+const WATCHDOG_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/* off-time for power-cycle commands without explicit delay, and the longest one accepted */
+const CYCLE_DEFAULT_DELAY: u32 = 5;
+const CYCLE_MAX_DELAY: u32 = 3600;
+
+/* time a power-cycled receptacle may differ from its desired state beyond the off-time */
+const DRIFT_GRACE: Duration = Duration::from_secs(30);
+
+/* delay before retrying a reconciliation that was refused or failed */
+const RECONCILE_RETRY: Duration = Duration::from_secs(300);
+
+/* recheck interval for enable commands queued by the branch load budget */
+const BUDGET_QUEUE_INTERVAL: Duration = Duration::from_secs(5);
+
+struct Reachability {
+    failures: std::collections::HashMap<u8, u32>,
+    online: std::collections::HashMap<u8, bool>,
+    last_success: Instant,
+    deadline: Option<Duration>,
+}
+
+impl Reachability {
+    fn new(deadline: Option<Duration>) -> Self {
+        Reachability {
+            failures: std::collections::HashMap::new(),
+            online: std::collections::HashMap::new(),
+            last_success: Instant::now(),
+            deadline: deadline,
+        }
+    }
+
+    /* PDU did not answer any request for longer than the configured deadline */
+    fn expired(self: &Self) -> bool {
+        match self.deadline {
+            Some(deadline) => self.last_success.elapsed() > deadline,
+            None => false,
+        }
+    }
+
+    /* returns availability message if the PDU's state changed */
+    fn update(self: &mut Self, pdu: u8, success: bool) -> Option<MQTTMsg> {
+        let failures = self.failures.entry(pdu).or_insert(0);
+        if success {
+            *failures = 0;
+            self.last_success = Instant::now();
+        } else {
+            *failures += 1;
+        }
+
+        let online = *failures < PDU_OFFLINE_THRESHOLD;
+        if self.online.insert(pdu, online) == Some(online) {
+            return None;
+        }
+
+        if !online {
+            eprintln!("PDU {} is unreachable", pdu);
+        }
+
+        Some(Reachability::message(pdu, online))
+    }
+
+    fn message(pdu: u8, online: bool) -> MQTTMsg {
+        MQTTMsg {
+            topic: format!("/pdu-{}/availability", pdu),
+            payload: format!("{}", if online { "online" } else { "offline" }),
+            retained: true,
+        }
+    }
+
+    fn any_offline(self: &Self) -> bool {
+        self.online.values().any(|online| !online)
+    }
+
+    /* current availability of all PDUs */
+    fn get_retained(self: &Self) -> MQTTMsgList {
+        self.online.iter().map(|(pdu, online)| Reachability::message(*pdu, *online)).collect()
+    }
+}
+
 enum Cache {
     MQTTMsgList(MQTTMsgList),
     EventList(liebert::EventList),
@@ -31,15 +142,21 @@ enum Cache {
 }
 
 impl Cache {
-    fn get_modified(self: &Self, new: &MQTTMsgList) -> MQTTMsgList {
+    /* the cache holds the last published values, so small changes can not add up unnoticed,
+     * republished silent values are not counted as significant changes */
+    fn get_modified(self: &Self, new: &MQTTMsgList, deadband: &deadband::Deadband, published: &std::collections::HashMap<String, Instant>) -> (MQTTMsgList, usize) {
         let mut result : MQTTMsgList = Vec::new();
+        let mut significant = 0;
 
         match self {
             Cache::MQTTMsgList(old) => {
                 for n in new {
                     for o in old {
                         if n.topic == o.topic {
-                            if n.payload != o.payload {
+                            if deadband.is_significant(&n.topic, &o.payload, &n.payload) {
+                                significant += 1;
+                                result.push(n.clone());
+                            } else if deadband.is_silent(published.get(&n.topic)) {
                                 result.push(n.clone());
                             }
                             break;
@@ -50,10 +167,11 @@ impl Cache {
             _ => {
                 /* no cached data => everything is new */
                 result.append(&mut new.clone());
+                significant = result.len();
             }
         }
         
-        result
+        (result, significant)
     }
 
     fn is_none(self: &Self) -> bool {
@@ -64,54 +182,211 @@ impl Cache {
     }
 }
 
-struct Task {
-    timestamp: Instant,
+/* polling state of a task, only locked briefly so commands never wait for a running request */
+struct TaskState {
+    next_run: Instant,
     timeout: Duration,
+    cache: Cache,
+    receptacle_state: Option<bool>,
+    failures: u32,
+    /* time of the last publish per topic */
+    published: std::collections::HashMap<String, Instant>,
+    /* all values of the last poll, for sinks that do not want the deadband applied */
+    samples: MQTTMsgList,
+}
+
+impl TaskState {
+    fn new(next_run: Instant, timeout: Duration) -> Self {
+        TaskState {
+            next_run,
+            timeout,
+            cache: Cache::None(()),
+            receptacle_state: None,
+            failures: 0,
+            published: std::collections::HashMap::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /* regular timeout, or exponential backoff while the task keeps failing */
+    fn interval(self: &Self) -> Duration {
+        if self.failures == 0 {
+            return self.timeout;
+        }
+
+        let factor = 1u32.checked_shl(self.failures - 1).unwrap_or(u32::MAX);
+        RETRY_BACKOFF_MIN.saturating_mul(factor).min(RETRY_BACKOFF_MAX)
+    }
+
+    fn timed_out(self: &Self) -> bool {
+        Instant::now() >= self.next_run
+    }
+
+    fn reschedule_in(self: &mut Self, seconds: u8) -> () {
+        self.next_run = Instant::now() + Duration::from_secs(seconds.into());
+    }
+}
+
+/* state shared by the tasks, commands and group sequences of a single PDU */
+struct PduContext {
+    pducfg: PDUCfg,
+    cfg: std::sync::Arc<Cfg>,
+    mpx: liebert::MPX,
+    publisher: Publisher,
+    sinks: sink::SinkList,
+    desired: desired::DesiredState,
+    protection: protection::Protection,
+    budget: budget::LoadBudget,
+}
+
+type TaskFuture<'a> = Pin<Box<dyn Future<Output = Result<MQTTMsgList, liebert::MPXError>> + Send + 'a>>;
+type TaskFn = for<'a> fn(&'a Task, &'a std::sync::Arc<Scheduler>) -> TaskFuture<'a>;
+
+struct Task {
     priority: TaskPriority,
-    function: fn(&'_ mut Task) -> Pin<Box<dyn Future<Output = Result<MQTTMsgList, liebert::MPXError>> + Send + '_>>,
-    mpx: std::sync::Arc<liebert::MPX>,
+    function: TaskFn,
+    ctx: std::sync::Arc<PduContext>,
     pdu: u8,
     branch: u8,
     receptacle: u8,
-    cache: Cache,
-    receptacle_state: Option<bool>,
+    adaptive: Option<Adaptive>,
+    state: std::sync::Mutex<TaskState>,
 }
 
 impl Task {
-    fn update_timestamp(self: &mut Self) -> () {
-        self.timestamp = Instant::now();
+    async fn run(self: &Self, scheduler: &std::sync::Arc<Scheduler>) -> Result<MQTTMsgList, liebert::MPXError> {
+        (self.function)(self, scheduler).await
+    }
+
+    /* poll faster while values keep changing, slow down again once they are stable */
+    fn adapt_interval(self: &Self, state: &mut TaskState, changes: usize) -> () {
+        if let Some(adaptive) = self.adaptive {
+            if changes > adaptive.threshold {
+                state.timeout = (state.timeout / 2).max(adaptive.min);
+            } else {
+                state.timeout = (state.timeout * 3 / 2).min(adaptive.max);
+            }
+        }
     }
 
-    async fn run(self: &mut Self) -> Result<MQTTMsgList, liebert::MPXError> {
-        let result = (self.function)(self).await;
-        self.update_timestamp();
+    /* returns the modified messages, the initial poll does not count as change */
+    fn update_cache(self: &Self, new: MQTTMsgList, document: MQTTMsg) -> MQTTMsgList {
+        let mut state = self.state.lock().unwrap();
+        let (mut result, significant) = state.cache.get_modified(&new, &self.ctx.cfg.deadband, &state.published);
+        state.samples = new.clone();
+        if !state.cache.is_none() {
+            self.adapt_interval(&mut state, significant);
+        }
+
+        /* keep the previously published value for suppressed changes */
+        let previous = match &state.cache {
+            Cache::MQTTMsgList(old) => old.iter().find(|o| o.topic == document.topic).cloned(),
+            _ => None,
+        };
+        let cache = match &state.cache {
+            Cache::MQTTMsgList(old) => new.into_iter().map(|n| {
+                if result.iter().any(|r| r.topic == n.topic) {
+                    n
+                } else {
+                    old.iter().find(|o| o.topic == n.topic).cloned().unwrap_or(n)
+                }
+            }).collect(),
+            _ => new,
+        };
+        state.cache = Cache::MQTTMsgList(cache);
+
+        /* the document is published together with modified values and kept until then */
+        if self.ctx.cfg.state_format.json() {
+            let document = match previous {
+                Some(previous) if result.is_empty() => previous,
+                _ => document,
+            };
+            if !result.is_empty() {
+                result.push(document.clone());
+            }
+            if let Cache::MQTTMsgList(cache) = &mut state.cache {
+                cache.push(document);
+            }
+        }
+
+        let now = Instant::now();
+        for msg in &result {
+            state.published.insert(msg.topic.clone(), now);
+        }
+
         result
     }
 
-    fn timed_out(self: &mut Self) -> bool {
-        self.timestamp.elapsed() > self.timeout
+    fn level(self: &Self) -> &'static str {
+        if self.pdu == 0 {
+            "events"
+        } else if self.branch == 0 {
+            "pdu"
+        } else if self.receptacle == 0 {
+            "branch"
+        } else {
+            "receptacle"
+        }
+    }
+
+    fn path(self: &Self) -> String {
+        if self.pdu == 0 {
+            "".to_string()
+        } else if self.branch == 0 {
+            format!("/pdu-{}", self.pdu)
+        } else if self.receptacle == 0 {
+            format!("/pdu-{}/branch-{}", self.pdu, self.branch)
+        } else {
+            format!("/pdu-{}/branch-{}/receptacle-{}", self.pdu, self.branch, self.receptacle)
+        }
     }
 
-    fn reschedule_in(self: &mut Self, seconds: u8) -> () {
-        let offset = Duration::from_secs(seconds.into());
-        self.timestamp = Instant::now().checked_sub(self.timeout).expect("Time out of bounds").checked_add(offset).expect("Time out of bounds");
+    /* track failures and turn the task result into messages for MQTT */
+    fn process_result(self: &Self, result: Result<MQTTMsgList, liebert::MPXError>, start: Instant, reachability: &mut Reachability) -> MQTTMsgList {
+        let mut state = self.state.lock().unwrap();
+        let error_topic = format!("{}/poll-error", self.path());
+        let success = result.is_ok();
+        let mut messages = match result {
+            Ok(mut messages) => {
+                if state.failures > 0 {
+                    println!("Task for {}.{}.{} recovered after {} failures", self.pdu, self.branch, self.receptacle, state.failures);
+                    /* empty retained message clears the error */
+                    messages.push(MQTTMsg { topic: error_topic, payload: "".to_string(), retained: true });
+                }
+                state.failures = 0;
+                messages
+            },
+            Err(e) => {
+                state.failures += 1;
+                eprintln!("Failed to run task for {}.{}.{} ({} failures, retry in {}s): {:?}", self.pdu, self.branch, self.receptacle, state.failures, state.interval().as_secs(), e);
+                vec![MQTTMsg { topic: error_topic, payload: format!("{:?}", e), retained: true }]
+            },
+        };
+
+        /* the interval depends on the failures, so the next run is scheduled afterwards,
+         * unless a command rescheduled the task while its request was running */
+        if state.next_run <= start {
+            state.next_run = Instant::now() + state.interval();
+        }
+
+        /* only tasks talking to a PDU module are relevant for its reachability */
+        if self.pdu != 0 {
+            if let Some(msg) = reachability.update(self.pdu, success) {
+                messages.push(msg);
+            }
+        }
+
+        messages
     }
 }
 
 type TaskList = Vec<Task>;
 
 trait TaskListFunctions {
-    fn append_systemd_watchdog(&mut self, mpx: std::sync::Arc<liebert::MPX>) -> ();
-    fn get_oldest(&mut self) -> Option<&mut Task>;
     fn contains(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> bool;
-    fn reschedule_in(self: &mut Self, pdu: u8, branch: u8, receptacle: u8, seconds: u8) -> ();
 }
 
 impl TaskListFunctions for TaskList {
-    fn get_oldest(self: &mut Self) -> Option<&mut Task> {
-        self.iter_mut().max_by(|a, b| a.timestamp.elapsed().partial_cmp(&b.timestamp.elapsed()).unwrap_or(std::cmp::Ordering::Less))
-    }
-
     fn contains(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> bool {
         for task in self {
             if task.pdu == pdu && task.branch == branch && task.receptacle == receptacle {
@@ -121,60 +396,231 @@ impl TaskListFunctions for TaskList {
 
         false
     }
+}
 
-    fn reschedule_in(self: &mut Self, pdu: u8, branch: u8, receptacle: u8, seconds: u8) -> () {
-        for task in self {
-            if task.pdu == pdu && task.branch == branch && task.receptacle == receptacle {
-                task.reschedule_in(seconds);
+/* a task shared between its own polling timer and the command handling */
+#[derive(Clone)]
+struct ScheduledTask {
+    pdu: u8,
+    branch: u8,
+    receptacle: u8,
+    level: &'static str,
+    priority: TaskPriority,
+    task: std::sync::Arc<Task>,
+    wakeup: std::sync::Arc<Notify>,
+}
+
+/* every task runs on its own tokio timer, concurrent PDU requests are bounded by a semaphore */
+struct Scheduler {
+    tasks: Vec<ScheduledTask>,
+    requests: std::sync::Arc<Semaphore>,
+    reachability: std::sync::Arc<std::sync::Mutex<Reachability>>,
+    stats: std::sync::Arc<metrics::Stats>,
+}
+
+impl Scheduler {
+    fn new(tasklist: TaskList, max_requests: usize, reachability: Reachability) -> Self {
+        let tasks = tasklist.into_iter().map(|task| ScheduledTask {
+            pdu: task.pdu,
+            branch: task.branch,
+            receptacle: task.receptacle,
+            level: task.level(),
+            priority: task.priority,
+            task: std::sync::Arc::new(task),
+            wakeup: std::sync::Arc::new(Notify::new()),
+        }).collect();
+
+        Scheduler {
+            tasks: tasks,
+            requests: std::sync::Arc::new(Semaphore::new(max_requests)),
+            reachability: std::sync::Arc::new(std::sync::Mutex::new(reachability)),
+            stats: std::sync::Arc::new(metrics::Stats::default()),
+        }
+    }
+
+    fn start(self: &std::sync::Arc<Self>, sinks: &sink::SinkList) -> () {
+        for t in &self.tasks {
+            tokio::spawn(run_task(self.clone(), t.clone(), sinks.clone()));
+        }
+    }
+
+    fn find(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> impl Iterator<Item = &ScheduledTask> {
+        self.tasks.iter().filter(move |t| t.pdu == pdu && t.branch == branch && t.receptacle == receptacle)
+    }
+
+    fn reschedule_in(self: &Self, pdu: u8, branch: u8, receptacle: u8, seconds: u8) -> () {
+        for t in self.find(pdu, branch, receptacle) {
+            t.task.state.lock().unwrap().reschedule_in(seconds);
+            t.wakeup.notify_one();
+        }
+    }
+
+    fn port_is_enabled(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> bool {
+        for t in self.find(pdu, branch, receptacle) {
+            if let Some(state) = t.task.state.lock().unwrap().receptacle_state {
+                return state;
             }
         }
+
+        false
     }
 
-    fn append_systemd_watchdog(self: &mut Self, mpx: std::sync::Arc<liebert::MPX>) -> () {
-        self.push(Task {
-            timestamp: Instant::now(),
-            timeout: Duration::from_secs(30),
-            priority: TaskPriority::HIGH,
-            function: |t| Box::pin(systemd_watchdog_update(t)),
-            mpx: mpx,
-            pdu: 0,
-            branch: 0,
-            receptacle: 0,
-            cache: Cache::None(()),
-            receptacle_state: None,
-        });
+    /* last known state of all retained topics */
+    fn get_retained(self: &Self) -> MQTTMsgList {
+        let mut result : MQTTMsgList = Vec::new();
+
+        for t in &self.tasks {
+            match &t.task.state.lock().unwrap().cache {
+                Cache::MQTTMsgList(messages) => {
+                    result.extend(messages.iter().filter(|m| m.retained).cloned());
+                },
+                Cache::EventList(events) => {
+                    result.push(mqttify::active_alarms_to_mqtt(events, "/active-alarms"));
+                },
+                _ => {},
+            }
+        }
+
+        result.append(&mut self.reachability.lock().unwrap().get_retained());
+        result
+    }
+
+    /* all values of the last polls, including the non-retained ones */
+    fn get_cached(self: &Self) -> MQTTMsgList {
+        let mut result : MQTTMsgList = Vec::new();
+
+        for t in &self.tasks {
+            if let Cache::MQTTMsgList(messages) = &t.task.state.lock().unwrap().cache {
+                result.extend(messages.iter().cloned());
+            }
+        }
+
+        result
+    }
+
+    fn is_ready(self: &Self) -> bool {
+        for t in &self.tasks {
+            /* ignore tasks not involving PDU requests */
+            if t.pdu == 0 {
+                continue;
+            }
+
+            /* the cache stays empty until the first request succeeded */
+            if t.task.state.lock().unwrap().cache.is_none() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn expired(self: &Self) -> bool {
+        self.reachability.lock().unwrap().expired()
+    }
+
+    fn unreachable(self: &Self) -> bool {
+        self.reachability.lock().unwrap().any_offline()
+    }
+}
+
+async fn run_task(scheduler: std::sync::Arc<Scheduler>, scheduled: ScheduledTask, sinks: sink::SinkList) {
+    let task = scheduled.task;
+
+    loop {
+        let next = task.state.lock().unwrap().next_run;
+
+        /* rescheduled tasks are woken up to recalculate their timer */
+        tokio::select! {
+            _ = tokio::time::sleep_until(next.into()) => {},
+            _ = scheduled.wakeup.notified() => { continue; },
+        }
+
+        /* high priority tasks do not wait for a free request slot, so max-requests only bounds the regular polls */
+        let permit = match scheduled.priority {
+            TaskPriority::HIGH => None,
+            TaskPriority::LOW => Some(scheduler.requests.acquire().await.expect("request limit has been closed")),
+        };
+
+        if !task.state.lock().unwrap().timed_out() {
+            continue;
+        }
+
+        let start = Instant::now();
+        let result = task.run(&scheduler).await;
+        scheduler.stats.record_poll(scheduled.level, start.elapsed(), result.is_ok());
+        let messages = task.process_result(result, start, &mut scheduler.reachability.lock().unwrap());
+        let samples = std::mem::take(&mut task.state.lock().unwrap().samples);
+        drop(permit);
+
+        for sink in &sinks {
+            sink.write(if sink.every_sample() { &samples } else { &messages }).await;
+        }
     }
 }
 
-async fn read_receptacle(task: &mut Task) -> Result<MQTTMsgList, liebert::MPXError> {
-    let info = task.mpx.get_info_receptacle(task.pdu, task.branch, task.receptacle).await?;
-    task.receptacle_state = Some(info.settings.power_state);
+async fn read_receptacle(task: &Task, scheduler: &std::sync::Arc<Scheduler>) -> Result<MQTTMsgList, liebert::MPXError> {
+    let info = task.ctx.mpx.get_info_receptacle(task.pdu, task.branch, task.receptacle).await?;
+    task.state.lock().unwrap().receptacle_state = Some(info.settings.power_state);
     let path = format!("/pdu-{}/branch-{}/receptacle-{}", task.pdu, task.branch, task.receptacle);
-    let new = info.to_mqtt(&path);
-    let result = task.cache.get_modified(&new);
-    task.cache = Cache::MQTTMsgList(new);
+    let actual = info.settings.power_state;
+    task.ctx.budget.update_receptacle(task.pdu, task.branch, task.receptacle, actual, info.status.current);
+    let document = info.to_state(&path, task.ctx.cfg.units);
+    let new = info.to_mqtt(&path, task.ctx.cfg.units);
+    let mut result = task.update_cache(new, document);
+
+    /* e.g. receptacles coming back in their default state after a PDU power loss */
+    if let Some((desired, is_new)) = task.ctx.desired.check(task.pdu, task.branch, task.receptacle, actual) {
+        let mut action = task.ctx.desired.action;
+        if !desired && task.ctx.protection.is_protected(task.pdu, task.branch, task.receptacle) {
+            action = desired::DriftAction::Alert;
+        }
+        if is_new {
+            eprintln!("Receptacle {}.{}.{} is {} instead of {} - {}", task.pdu, task.branch, task.receptacle, if actual { "on" } else { "off" }, if desired { "on" } else { "off" }, action.describe());
+            let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            result.push(mqttify::drift_to_mqtt(task.pdu, task.branch, task.receptacle, desired, &action.describe(), "/alerts", timestamp));
+        }
+
+        /* dependencies and the budget apply as for commands,
+         * the drift is already reported, so only retry after a while instead of on every poll */
+        if action == desired::DriftAction::Reconcile {
+            let target = (task.pdu, task.branch, task.receptacle);
+            let result = if desired {
+                let set = enable_set(target, &task.ctx, scheduler);
+                match task.ctx.budget.check(&set) {
+                    Ok(()) => enable_receptacles(target, &set, &task.ctx, scheduler).await,
+                    Err(e) => Err(e),
+                }
+            } else {
+                match disable_dependents(target, &task.ctx, scheduler).await {
+                    Ok(_) => retry_cmd(&task.ctx.mpx, task.pdu, task.branch, task.receptacle, liebert::ReceptacleCmd::Disable).await.map_err(|e| format!("{:?}", e)),
+                    Err(e) => Err(e),
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to reconcile receptacle {}.{}.{}, retrying in {} seconds: {}", task.pdu, task.branch, task.receptacle, RECONCILE_RETRY.as_secs(), e);
+                task.ctx.desired.suspend(task.pdu, task.branch, task.receptacle, RECONCILE_RETRY);
+            }
+        }
+    }
 
     Ok(result)
 }
 
-async fn read_branch(task: &mut Task) -> Result<MQTTMsgList, liebert::MPXError> {
-    let info = task.mpx.get_info_branch(task.pdu, task.branch).await?;
+async fn read_branch(task: &Task) -> Result<MQTTMsgList, liebert::MPXError> {
+    let info = task.ctx.mpx.get_info_branch(task.pdu, task.branch).await?;
     let path = format!("/pdu-{}/branch-{}", task.pdu, task.branch);
-    let new = info.to_mqtt(&path);
-    let result = task.cache.get_modified(&new);
-    task.cache = Cache::MQTTMsgList(new);
-
-    Ok(result)
+    task.ctx.budget.update_branch(task.pdu, task.branch, info.status.current, info.status.current_available_to_alarm);
+    let document = info.to_state(&path, task.ctx.cfg.units);
+    let new = info.to_mqtt(&path, task.ctx.cfg.units);
+    Ok(task.update_cache(new, document))
 }
 
-async fn read_pdu(task: &mut Task) -> Result<MQTTMsgList, liebert::MPXError> {
-    let info = task.mpx.get_info_pdu(task.pdu).await?;
+async fn read_pdu(task: &Task) -> Result<MQTTMsgList, liebert::MPXError> {
+    let info = task.ctx.mpx.get_info_pdu(task.pdu).await?;
     let path = format!("/pdu-{}", task.pdu);
-    let new = info.to_mqtt(&path);
-    let result = task.cache.get_modified(&new);
-    task.cache = Cache::MQTTMsgList(new);
-
-    Ok(result)
+    let document = info.to_state(&path, task.ctx.cfg.units);
+    let new = info.to_mqtt(&path, task.ctx.cfg.units);
+    Ok(task.update_cache(new, document))
 }
 
 fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
@@ -182,116 +628,152 @@ fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
     matching == a.len() && matching == b.len()
 }
 
-async fn systemd_watchdog_update(_task: &mut Task) -> Result<MQTTMsgList, liebert::MPXError> {
-    let result: MQTTMsgList = Vec::new();
-    let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]);
-    Ok(result)
-}
-
-async fn read_events(task: &mut Task) -> Result<MQTTMsgList, liebert::MPXError> {
-    let result: MQTTMsgList = Vec::new();
-    let events = task.mpx.get_events().await?;
+async fn read_events(task: &Task, scheduler: &std::sync::Arc<Scheduler>) -> Result<MQTTMsgList, liebert::MPXError> {
+    let mut result: MQTTMsgList = Vec::new();
+    let events = task.ctx.mpx.get_events().await?;
+    let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
 
     /* 1. check if anything changed from previous state */
-    match &task.cache {
+    let is_new : Vec<bool> = match &task.state.lock().unwrap().cache {
         Cache::EventList(cache) => {
             if do_vecs_match(cache, &events) {
                 /* no new events */
                 return Ok(result)
             }
+            events.iter().map(|event| !cache.contains(event)).collect()
         },
-        _ => { /* empty cache */ }
-    }
-
-    /* 2. handle events */
-    for event in &events {
-        match event.event {
-            liebert::EventType::ReceptacleOverCurrent => {
-                match event.level {
-                    liebert::EventLevel::ALARM => {
-                        /* disable receptacle */
-                        eprintln!("over-current alarm for receptacle {}.{}.{} - disabling", event.pdu, event.branch, event.receptacle);
-                        retry_cmd(&task.mpx, event.pdu, event.branch, event.receptacle, liebert::ReceptacleCmd::Disable).await;
-                    },
-                    liebert::EventLevel::WARNING => {
-                        eprintln!("over-current warning for receptacle {}.{}.{} - ignoring", event.pdu, event.branch, event.receptacle);
-                    },
-                    _ => {
-                        /* PDU does not seem to generate OK or INFO over-current events */
-                        println!("unhandled event: {:?}", event);
-                    },
+        _ => { /* empty cache */ vec![true; events.len()] }
+    };
+
+    /* 2. handle new events */
+    for (event, is_new) in events.iter().zip(is_new) {
+        if !is_new {
+            continue;
+        }
+
+        result.push(mqttify::event_to_mqtt(event, "/events", timestamp));
+
+        let mut action = task.ctx.pducfg.event_policy.get(event);
+        if action != Action::Ignore && action != Action::Notify && event.receptacle == 0 {
+            eprintln!("{:?} can only be handled for receptacle events, notifying instead", action);
+            action = Action::Notify;
+        }
+
+        /* automatic disables of protected receptacles need an override as well, dependents are handled as for commands */
+        let mut description = action.describe();
+        let mut cascaded = Vec::new();
+        if matches!(action, Action::Disable | Action::DisableReenable(_)) {
+            let target = (event.pdu, event.branch, event.receptacle);
+            let result = match task.ctx.protection.check(event.pdu, event.branch, event.receptacle) {
+                Ok(()) => disable_dependents(target, &task.ctx, scheduler).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(disabled) => cascaded = disabled,
+                Err(e) => {
+                    eprintln!("{:?} {:?} for receptacle {}.{}.{} - not disabling: {}", event.event, event.level, event.pdu, event.branch, event.receptacle, e);
+                    description = format!("{} rejected: {}", description, e);
+                    action = Action::Notify;
+                },
+            }
+        }
+
+        match action {
+            Action::Ignore => {
+                println!("ignoring event: {:?}", event);
+                continue;
+            },
+            Action::Notify => {
+                eprintln!("event: {:?}", event);
+            },
+            Action::Disable => {
+                eprintln!("{:?} {:?} for receptacle {}.{}.{} - disabling", event.event, event.level, event.pdu, event.branch, event.receptacle);
+                task.ctx.protection.consume(event.pdu, event.branch, event.receptacle);
+                let _ = retry_cmd(&task.ctx.mpx, event.pdu, event.branch, event.receptacle, liebert::ReceptacleCmd::Disable).await;
+                if let Err(e) = task.ctx.desired.set(event.pdu, event.branch, event.receptacle, false) {
+                    description = format!("{}, {}", description, e);
                 }
             },
-            _ => {
-                println!("unhandled event: {:?}", event);
+            Action::DisableReenable(seconds) => {
+                eprintln!("{:?} {:?} for receptacle {}.{}.{} - disabling for {} seconds", event.event, event.level, event.pdu, event.branch, event.receptacle, seconds);
+                task.ctx.desired.suspend(event.pdu, event.branch, event.receptacle, Duration::from_secs(seconds.into()) + DRIFT_GRACE);
+                task.ctx.protection.consume(event.pdu, event.branch, event.receptacle);
+                let _ = retry_cmd(&task.ctx.mpx, event.pdu, event.branch, event.receptacle, liebert::ReceptacleCmd::Disable).await;
+
+                /* re-enable in the background to keep the scheduler going */
+                let ctx = task.ctx.clone();
+                let scheduler = scheduler.clone();
+                let target = (event.pdu, event.branch, event.receptacle);
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(seconds.into())).await;
+                    if let Err(e) = reenable(target, cascaded, &ctx, &scheduler).await {
+                        eprintln!("Not re-enabling receptacle {}.{}.{}: {}", target.0, target.1, target.2, e);
+                    }
+                });
             },
         }
+
+        result.push(mqttify::alert_to_mqtt(event, "/alerts", &description, timestamp));
     }
 
     /* 3. update cache */
-    task.cache = Cache::EventList(events);
+    result.push(mqttify::active_alarms_to_mqtt(&events, "/active-alarms"));
+    task.state.lock().unwrap().cache = Cache::EventList(events);
 
     Ok(result)
 }
 
-async fn setup_tasklist(mpx: std::sync::Arc<liebert::MPX>, receptacles: &liebert::ReceptacleList) -> Result<TaskList, liebert::MPXError> {
+async fn setup_tasklist(ctx: &std::sync::Arc<PduContext>, receptacles: &liebert::ReceptacleList) -> Result<TaskList, liebert::MPXError> {
+    let intervals = &ctx.pducfg.intervals;
     let mut tasklist = Vec::new();
 
     tasklist.push(Task {
-        timestamp: Instant::now(),
-        timeout: Duration::from_secs(3),
         priority: TaskPriority::HIGH,
-        function: |t| Box::pin(read_events(t)),
-        mpx: mpx.clone(),
+        function: |t, s| Box::pin(read_events(t, s)),
+        ctx: ctx.clone(),
         pdu: 0,
         branch: 0,
         receptacle: 0,
-        cache: Cache::None(()),
-        receptacle_state: None,
+        adaptive: None,
+        state: std::sync::Mutex::new(TaskState::new(Instant::now() + intervals.events, intervals.events)),
     });
 
     for r in receptacles {
         if !tasklist.contains(r.pdu, 0, 0) {
             tasklist.push(Task {
-                timestamp: Instant::now(),
-                timeout: Duration::from_secs(30),
                 priority: TaskPriority::LOW,
-                function: |t| Box::pin(read_pdu(t)),
-                mpx: mpx.clone(),
+                function: |t, _| Box::pin(read_pdu(t)),
+                ctx: ctx.clone(),
                 pdu: r.pdu,
                 branch: 0,
                 receptacle: 0,
-                cache: Cache::None(()),
-                receptacle_state: None,
+                adaptive: intervals.adaptive,
+                state: std::sync::Mutex::new(TaskState::new(Instant::now() + intervals.pdu, intervals.pdu)),
             });
         }
 
         if !tasklist.contains(r.pdu, r.branch, 0) {
             tasklist.push(Task {
-                timestamp: Instant::now().checked_sub(Duration::from_secs((r.branch*10).into())).unwrap(),
-                timeout: Duration::from_secs(30),
                 priority: TaskPriority::LOW,
-                function: |t| Box::pin(read_branch(t)),
-                mpx: mpx.clone(),
+                function: |t, _| Box::pin(read_branch(t)),
+                ctx: ctx.clone(),
                 pdu: r.pdu,
                 branch: r.branch,
                 receptacle: 0,
-                cache: Cache::None(()),
-                receptacle_state: None,
+                adaptive: intervals.adaptive,
+                state: std::sync::Mutex::new(TaskState::new(Instant::now() + intervals.branch.saturating_sub(Duration::from_secs((r.branch*10).into())), intervals.branch)),
             });
         }
 
         tasklist.push(Task {
-            timestamp: Instant::now().checked_sub(Duration::from_secs((r.branch*10).into())).unwrap(),
-            timeout: Duration::from_secs(30),
             priority: TaskPriority::LOW,
-            function: |t| Box::pin(read_receptacle(t)),
-            mpx: mpx.clone(),
+            function: |t, s| Box::pin(read_receptacle(t, s)),
+            ctx: ctx.clone(),
             pdu: r.pdu,
             branch: r.branch,
             receptacle: r.receptacle,
-            cache: Cache::None(()),
-            receptacle_state: None,
+            adaptive: intervals.receptacle_adaptive(r.pdu, r.branch, r.receptacle),
+            state: std::sync::Mutex::new(TaskState::new(Instant::now() + intervals.receptacle(r.pdu, r.branch, r.receptacle).saturating_sub(Duration::from_secs((r.branch*10).into())), intervals.receptacle(r.pdu, r.branch, r.receptacle))),
         });
     }
 
@@ -301,100 +783,713 @@ async fn setup_tasklist(mpx: std::sync::Arc<liebert::MPX>, receptacles: &liebert
     Ok(tasklist)
 }
 
-#[derive(Debug)]
+#[derive(Copy,Clone,Debug,PartialEq)]
+enum Setting {
+    Label,
+    AssetTag1,
+    AssetTag2,
+    OverCurrentAlarmThreshold,
+    OverCurrentWarningThreshold,
+    LowCurrentAlarmThreshold,
+    PowerOnDelay,
+    L1LowCurrentAlarmThreshold,
+    L1OverCurrentAlarmThreshold,
+    L1OverCurrentWarningThreshold,
+    L2LowCurrentAlarmThreshold,
+    L2OverCurrentAlarmThreshold,
+    L2OverCurrentWarningThreshold,
+    L3LowCurrentAlarmThreshold,
+    L3OverCurrentAlarmThreshold,
+    L3OverCurrentWarningThreshold,
+    NOverCurrentAlarmThreshold,
+    NOverCurrentWarningThreshold,
+}
+
+const SETTINGS: &[(Setting, &str)] = &[
+    (Setting::Label, "set-label"),
+    (Setting::AssetTag1, "set-asset-tag-1"),
+    (Setting::AssetTag2, "set-asset-tag-2"),
+    (Setting::OverCurrentAlarmThreshold, "set-over-current-alarm-threshold"),
+    (Setting::OverCurrentWarningThreshold, "set-over-current-warning-threshold"),
+    (Setting::LowCurrentAlarmThreshold, "set-low-current-alarm-threshold"),
+    (Setting::PowerOnDelay, "set-power-on-delay"),
+    (Setting::L1LowCurrentAlarmThreshold, "set-l1-low-current-alarm-threshold"),
+    (Setting::L1OverCurrentAlarmThreshold, "set-l1-over-current-alarm-threshold"),
+    (Setting::L1OverCurrentWarningThreshold, "set-l1-over-current-warning-threshold"),
+    (Setting::L2LowCurrentAlarmThreshold, "set-l2-low-current-alarm-threshold"),
+    (Setting::L2OverCurrentAlarmThreshold, "set-l2-over-current-alarm-threshold"),
+    (Setting::L2OverCurrentWarningThreshold, "set-l2-over-current-warning-threshold"),
+    (Setting::L3LowCurrentAlarmThreshold, "set-l3-low-current-alarm-threshold"),
+    (Setting::L3OverCurrentAlarmThreshold, "set-l3-over-current-alarm-threshold"),
+    (Setting::L3OverCurrentWarningThreshold, "set-l3-over-current-warning-threshold"),
+    (Setting::NOverCurrentAlarmThreshold, "set-n-over-current-alarm-threshold"),
+    (Setting::NOverCurrentWarningThreshold, "set-n-over-current-warning-threshold"),
+];
+
+impl Setting {
+    fn from_command(cmd: &str) -> Option<Setting> {
+        SETTINGS.iter().find(|(_, name)| *name == cmd).map(|(setting, _)| *setting)
+    }
+
+    /* l1-over-current-alarm => set-l1-over-current-alarm-threshold */
+    fn from_threshold(name: &str) -> Option<Setting> {
+        Setting::from_command(&format!("set-{}-threshold", name))
+    }
+
+    fn command(self: &Self) -> &'static str {
+        SETTINGS.iter().find(|(setting, _)| setting == self).map(|(_, name)| *name).unwrap_or("set")
+    }
+
+    fn is_text(self: &Self) -> bool {
+        match self {
+            Setting::Label | Setting::AssetTag1 | Setting::AssetTag2 => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone,Debug)]
 enum Command {
     Enable,
     Disable,
     Toggle,
     Identify,
-    SetLabel,
+    Set(Setting),
+    Cycle(u32),
+    /* token in the payload, permits the next disable of a protected receptacle */
+    Override,
+    /* group sequences */
+    EnableAll,
+    DisableAll,
+    Cancel,
+}
+
+impl Command {
+    fn name(self: &Self) -> &'static str {
+        match self {
+            Command::Enable => "enable",
+            Command::Disable => "disable",
+            Command::Toggle => "toggle",
+            Command::Identify => "identify",
+            Command::Set(setting) => setting.command(),
+            Command::Cycle(_) => "cycle",
+            Command::Override => "override",
+            Command::EnableAll => "enable-all",
+            Command::DisableAll => "disable-all",
+            Command::Cancel => "cancel",
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 struct Query {
-    cmd: Option<Command>,
+    cmd: Result<Command, String>,
     pdu: u8,
     branch: u8,
     receptacle: u8,
     payload: Option<String>,
+    id: Option<String>,
+    /* receptacle group, pdu, branch and receptacle are 0 for groups */
+    group: Option<String>,
 }
 
-fn parse_incoming_msg(msg: rumqttc::v4::Publish) -> Query {
-    let re = regex::Regex::new(r".*?/pdu-(?P<pdu>\d+)/branch-(?P<branch>\d+)/receptacle-(?P<receptacle>\d+)/control").unwrap();
-    let caps = re.captures(&msg.topic).expect("received topic has not been subscribed");
-    let pdu = caps["pdu"].parse::<u8>().expect("Failed to parse pdu");
-    let branch = caps["branch"].parse::<u8>().expect("Failed to parse branch");
-    let receptacle = caps["receptacle"].parse::<u8>().expect("Failed to parse receptacle");
-    let mut cmd = match &msg.payload[..] {
-        b"enable" => Some(Command::Enable),
-        b"disable" => Some(Command::Disable),
-        b"toggle" => Some(Command::Toggle),
-        b"identify" => Some(Command::Identify),
-        _ => None,
-    };
-    let mut payload = None;
-
-    if cmd.is_none() {
-        let re = regex::Regex::new(r"set-label (?P<label>[A-Za-z0-9-_.]+)").unwrap();
-        let msgpayload = std::str::from_utf8(&msg.payload);
-        if msgpayload.is_ok() {
-            let caps = re.captures(msgpayload.unwrap());
-            if caps.is_some() {
-                payload = Some(caps.unwrap()["label"].to_string());
-                cmd = Some(Command::SetLabel);
-            }
+#[derive(Serialize)]
+struct CommandResult<'a> {
+    cmd: Option<&'a str>,
+    target: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+}
+
+impl Query {
+    fn path(self: &Self) -> String {
+        if let Some(group) = &self.group {
+            format!("/group-{}", group)
+        } else if self.branch == 0 {
+            format!("/pdu-{}", self.pdu)
+        } else if self.receptacle == 0 {
+            format!("/pdu-{}/branch-{}", self.pdu, self.branch)
+        } else {
+            format!("/pdu-{}/branch-{}/receptacle-{}", self.pdu, self.branch, self.receptacle)
         }
     }
 
-    Query { cmd, pdu, branch, receptacle, payload }
-}
+    fn target(self: &Self) -> String {
+        if let Some(group) = &self.group {
+            group.clone()
+        } else if self.branch == 0 {
+            format!("{}", self.pdu)
+        } else if self.receptacle == 0 {
+            format!("{}.{}", self.pdu, self.branch)
+        } else {
+            format!("{}.{}.{}", self.pdu, self.branch, self.receptacle)
+        }
+    }
 
-struct Cfg {
-    mqtt_address: String,
-    mqtt_port: u16,
-    mqtt_username: String,
-    mqtt_password: String,
-    mqtt_clientname: String,
-    mqtt_prefix: String,
-    mqtt_no_retained: bool,
-    pdu_address: String,
-    pdu_username: String,
-    pdu_password: String,
+    /* acknowledgement published to <path>/result */
+    fn result(self: &Self, result: &Result<(), String>) -> MQTTMsg {
+        let msg = CommandResult {
+            cmd: self.cmd.as_ref().ok().map(|c| c.name()),
+            target: self.target(),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.as_str()),
+            id: self.id.as_deref(),
+        };
+
+        MQTTMsg {
+            topic: format!("{}/result", self.path()),
+            payload: serde_json::to_string(&msg).unwrap_or_default(),
+            retained: false,
+        }
+    }
 }
 
-fn get_config(filename: &str) -> Cfg {
-    let cfg = match Ini::load_from_file(filename) {
-        Err(ini::Error::Io(e)) => {
-            eprintln!("Failed to load config file \"{}\": {}", filename, e);
-            std::process::exit(1);
-        },
-        Err(ini::Error::Parse(e)) => {
-            eprintln!("Failed to parse config file \"{}\": {}", filename, e.msg);
-            std::process::exit(1);
-        },
-        Ok(cfg) => cfg,
+/* syntax check only, numeric values are validated against the PDU's settings when applied */
+fn parse_setting(setting: Setting, value: &str, query: &mut Query) -> Result<Command, String> {
+    let value = if setting.is_text() {
+        validate_label(value)?
+    } else {
+        value.trim().to_string()
     };
 
-    let mqtt = cfg.section(Some("MQTT")).expect("MQTT section mising in config");
-    let pdu = cfg.section(Some("PDU")).expect("PDU section mising in config");
+    query.payload = Some(value);
+    Ok(Command::Set(setting))
+}
 
-    Cfg {
-        mqtt_address: mqtt.get("address").expect("MQTT address missing in config").to_string(),
-        mqtt_port: mqtt.get("port").expect("MQTT port missing in config").parse::<u16>().expect("Failed to parse MQTT port in config"),
-        mqtt_username: mqtt.get("username").expect("MQTT username missing in config").to_string(),
-        mqtt_password: mqtt.get("password").expect("MQTT password missing in config").to_string(),
-        mqtt_clientname: mqtt.get("clientname").expect("MQTT client name missing in config").to_string(),
-        mqtt_prefix: mqtt.get("prefix").expect("MQTT prefix missing in config").to_string(),
-        mqtt_no_retained: std::str::FromStr::from_str(mqtt.get("avoid-retained").unwrap_or("false")).expect("Failed to parse avoid-retained"),
+/* the length limit is left to the PDU, which rejects over-long labels itself */
+fn validate_label(label: &str) -> Result<String, String> {
+    if label.chars().any(|c| c.is_control()) {
+        return Err("label contains control characters".to_string());
+    }
 
-        pdu_address: pdu.get("address").expect("PDU address missing in config").to_string(),
-        pdu_username: pdu.get("username").expect("PDU username missing in config").to_string(),
-        pdu_password: pdu.get("password").expect("PDU password missing in config").to_string(),
+    Ok(label.to_string())
+}
+
+/* the receptacle stays off for the whole delay, so typos like 300000 are rejected */
+fn cycle(delay: u32) -> Result<Command, String> {
+    if delay > CYCLE_MAX_DELAY {
+        return Err(format!("invalid delay: {} exceeds the maximum of {} seconds", delay, CYCLE_MAX_DELAY));
     }
+    Ok(Command::Cycle(delay))
 }
 
-async fn retry_cmd(mpx: &liebert::MPX, pdu: u8, branch: u8, receptacle: u8, cmd: liebert::ReceptacleCmd) {
-    let mut test = mpx.receptacle_command(pdu, branch, receptacle, cmd).await;
+fn parse_text_command(text: &str, query: &mut Query) -> () {
+    query.cmd = match text {
+        "enable" => Ok(Command::Enable),
+        "disable" => Ok(Command::Disable),
+        "toggle" => Ok(Command::Toggle),
+        "identify" => Ok(Command::Identify),
+        "enable-all" => Ok(Command::EnableAll),
+        "disable-all" => Ok(Command::DisableAll),
+        "cancel" => Ok(Command::Cancel),
+        _ => Err(format!("unknown command \"{}\"", text)),
+    };
+
+    if query.cmd.is_err() {
+        let re = regex::Regex::new(r"^cycle( (?P<delay>\d+))?$").unwrap();
+        if let Some(caps) = re.captures(text) {
+            query.cmd = match caps.name("delay").map(|d| d.as_str().parse::<u32>()) {
+                None => Ok(Command::Cycle(CYCLE_DEFAULT_DELAY)),
+                Some(Ok(delay)) => cycle(delay),
+                Some(Err(e)) => Err(format!("invalid delay: {}", e)),
+            };
+        }
+    }
+
+    if query.cmd.is_err() {
+        if let Some(token) = text.strip_prefix("override ") {
+            query.payload = Some(token.trim().to_string());
+            query.cmd = Ok(Command::Override);
+            return;
+        }
+    }
+
+    if query.cmd.is_err() {
+        let re = regex::Regex::new(r"^set-threshold (?P<name>[a-z0-9-]+) (?P<value>.+)$").unwrap();
+        if let Some(caps) = re.captures(text) {
+            query.cmd = match Setting::from_threshold(&caps["name"]) {
+                Some(setting) => parse_setting(setting, &caps["value"], query),
+                None => Err(format!("unknown threshold \"{}\"", &caps["name"])),
+            };
+            return;
+        }
+    }
+
+    if query.cmd.is_err() {
+        let re = regex::Regex::new(r"^(?P<cmd>set-[a-z0-9-]+) (?P<value>.+)$").unwrap();
+        if let Some(caps) = re.captures(text) {
+            if let Some(setting) = Setting::from_command(&caps["cmd"]) {
+                query.cmd = parse_setting(setting, &caps["value"], query);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonCommand {
+    cmd: String,
+    label: Option<String>,
+    threshold: Option<String>,
+    value: Option<serde_json::Value>,
+    delay: Option<u32>,
+    token: Option<String>,
+    id: Option<serde_json::Value>,
+}
+
+/*
+ * e.g. {"cmd":"set-label","label":"Rack 3 switch"}, {"cmd":"set-power-on-delay","value":5},
+ * {"cmd":"set-threshold","threshold":"l1-over-current-alarm","value":80}, {"cmd":"cycle","delay":10}
+ * or {"cmd":"override","token":"secret"}
+ */
+fn parse_json_command(text: &str, query: &mut Query) -> () {
+    let json : JsonCommand = match serde_json::from_str(text) {
+        Ok(json) => json,
+        Err(e) => {
+            query.cmd = Err(format!("invalid JSON command: {}", e));
+            return;
+        },
+    };
+
+    query.id = match json.id {
+        Some(serde_json::Value::String(id)) => Some(id),
+        Some(id) => Some(id.to_string()),
+        None => None,
+    };
+
+    let cmd = match (json.cmd.as_str(), &json.threshold) {
+        ("set-threshold", Some(name)) => format!("set-{}-threshold", name),
+        ("set-threshold", None) => {
+            query.cmd = Err("missing field threshold".to_string());
+            return;
+        },
+        (_, _) => json.cmd.clone(),
+    };
+
+    query.cmd = match cmd.as_str() {
+        "enable" => Ok(Command::Enable),
+        "disable" => Ok(Command::Disable),
+        "toggle" => Ok(Command::Toggle),
+        "identify" => Ok(Command::Identify),
+        "enable-all" => Ok(Command::EnableAll),
+        "disable-all" => Ok(Command::DisableAll),
+        "cancel" => Ok(Command::Cancel),
+        "cycle" => cycle(json.delay.unwrap_or(CYCLE_DEFAULT_DELAY)),
+        "override" => match &json.token {
+            Some(token) => {
+                query.payload = Some(token.clone());
+                Ok(Command::Override)
+            },
+            None => Err("override requires a token".to_string()),
+        },
+        cmd => match (Setting::from_command(cmd), &json.label, &json.value) {
+            (Some(Setting::Label), Some(label), None) => parse_setting(Setting::Label, label, query),
+            (Some(setting), None, Some(serde_json::Value::String(value))) => parse_setting(setting, value, query),
+            (Some(setting), None, Some(value)) if !setting.is_text() => parse_setting(setting, &value.to_string(), query),
+            (Some(_), _, _) => Err(format!("{} requires a single value", cmd)),
+            (None, _, _) => Err(format!("unknown command \"{}\"", cmd)),
+        },
+    };
+
+    if json.label.is_some() && json.cmd != "set-label" {
+        query.cmd = Err(format!("label is not supported by {}", json.cmd));
+    } else if json.value.is_some() && Setting::from_command(&cmd).is_none() {
+        query.cmd = Err(format!("value is not supported by {}", json.cmd));
+    } else if json.threshold.is_some() && json.cmd != "set-threshold" {
+        query.cmd = Err(format!("threshold is not supported by {}", json.cmd));
+    } else if json.delay.is_some() && json.cmd != "cycle" {
+        query.cmd = Err(format!("delay is not supported by {}", json.cmd));
+    } else if json.token.is_some() && json.cmd != "override" {
+        query.cmd = Err(format!("token is not supported by {}", json.cmd));
+    }
+}
+
+/* branch and receptacle are 0 for PDU and branch level control topics */
+/* None for topics that are not a control topic of a PDU, branch, receptacle or group */
+fn parse_incoming_msg(msg: rumqttc::v4::Publish) -> Option<Query> {
+    let group_re = regex::Regex::new(r".*?/group-(?P<group>[a-zA-Z0-9_-]+)/control$").unwrap();
+    let mut query = if let Some(caps) = group_re.captures(&msg.topic) {
+        Query { cmd: Err("empty command".to_string()), pdu: 0, branch: 0, receptacle: 0, payload: None, id: None, group: Some(caps["group"].to_string()) }
+    } else {
+        let re = regex::Regex::new(r".*?/pdu-(?P<pdu>\d+)(/branch-(?P<branch>\d+)(/receptacle-(?P<receptacle>\d+))?)?/control$").unwrap();
+        let caps = re.captures(&msg.topic)?;
+        let pdu = caps["pdu"].parse::<u8>().ok()?;
+        let branch = caps.name("branch").map_or(Ok(0), |b| b.as_str().parse::<u8>()).ok()?;
+        let receptacle = caps.name("receptacle").map_or(Ok(0), |r| r.as_str().parse::<u8>()).ok()?;
+        Query { cmd: Err("empty command".to_string()), pdu, branch, receptacle, payload: None, id: None, group: None }
+    };
+
+    match std::str::from_utf8(&msg.payload) {
+        Ok(text) if text.trim_start().starts_with('{') => parse_json_command(text, &mut query),
+        Ok(text) => parse_text_command(text, &mut query),
+        Err(_) => { query.cmd = Err("command is not valid UTF-8".to_string()); },
+    }
+
+    Some(query)
+}
+
+/* bounds for polling intervals adapting to the change rate */
+#[derive(Copy,Clone)]
+struct Adaptive {
+    min: Duration,
+    max: Duration,
+    /* number of modified values per poll considered as load change */
+    threshold: usize,
+}
+
+/* polling intervals for the PDU's tasks */
+#[derive(Clone)]
+struct Intervals {
+    events: Duration,
+    pdu: Duration,
+    branch: Duration,
+    default_receptacle: Duration,
+    receptacles: std::collections::HashMap<(u8, u8, u8), Duration>,
+    adaptive: Option<Adaptive>,
+}
+
+impl Intervals {
+    fn receptacle(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> Duration {
+        *self.receptacles.get(&(pdu, branch, receptacle)).unwrap_or(&self.default_receptacle)
+    }
+
+    /* explicitly configured receptacle intervals are kept fixed */
+    fn receptacle_adaptive(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> Option<Adaptive> {
+        if self.receptacles.contains_key(&(pdu, branch, receptacle)) {
+            None
+        } else {
+            self.adaptive
+        }
+    }
+}
+
+#[derive(Clone)]
+struct PDUCfg {
+    name: String,
+    address: String,
+    username: String,
+    password: String,
+    unreachable_timeout: Option<Duration>,
+    max_requests: usize,
+    intervals: Intervals,
+    desired_state: Option<String>,
+    drift_action: desired::DriftAction,
+    protected: Vec<(u8, u8, u8)>,
+    override_token: Option<String>,
+    groups: Vec<sequence::Group>,
+    budget_mode: budget::BudgetMode,
+    budget_queue_timeout: Duration,
+    dependencies: dependency::Dependencies,
+    event_policy: std::sync::Arc<policy::EventPolicy>,
+}
+
+impl PDUCfg {
+    /* topics of named PDUs are published below <prefix>/<name> */
+    fn topic_prefix(self: &Self) -> String {
+        if self.name.is_empty() {
+            "".to_string()
+        } else {
+            format!("/{}", self.name)
+        }
+    }
+}
+
+struct Cfg {
+    mqtt_address: String,
+    mqtt_port: u16,
+    mqtt_username: String,
+    mqtt_password: String,
+    mqtt_clientname: String,
+    mqtt_prefix: String,
+    mqtt_no_retained: bool,
+    hass_discovery: bool,
+    hass_prefix: String,
+    units: mqttify::Units,
+    state_format: mqttify::StateFormat,
+    pdus: Vec<PDUCfg>,
+    deadband: deadband::Deadband,
+    metrics_listen: Option<String>,
+    influxdb: Option<influxdb::InfluxCfg>,
+}
+
+fn parse_interval(key: &str, value: &str) -> Duration {
+    match value.parse::<u64>() {
+        Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+        _ => {
+            eprintln!("Failed to parse PDU {} in config, expected seconds > 0", key);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn get_adaptive_config(pdu: &ini::Properties) -> Option<Adaptive> {
+    let enabled : bool = std::str::FromStr::from_str(pdu.get("adaptive-polling").unwrap_or("false")).expect("Failed to parse PDU adaptive-polling in config");
+    if !enabled {
+        return None;
+    }
+
+    let adaptive = Adaptive {
+        min: parse_interval("adaptive-min-interval", pdu.get("adaptive-min-interval").unwrap_or("5")),
+        max: parse_interval("adaptive-max-interval", pdu.get("adaptive-max-interval").unwrap_or("300")),
+        threshold: pdu.get("adaptive-threshold").unwrap_or("2").parse::<usize>().expect("Failed to parse PDU adaptive-threshold in config"),
+    };
+
+    if adaptive.min > adaptive.max {
+        eprintln!("PDU adaptive-min-interval exceeds adaptive-max-interval in config");
+        std::process::exit(1);
+    }
+
+    Some(adaptive)
+}
+
+/* <level>-interval sets the defaults, receptacle-interval-<pdu>.<branch>.<receptacle> overrides them */
+/* pdu.branch.receptacle, e.g. 1.2.3 */
+/* relative paths are kept in the state directory systemd provides to the instance */
+fn state_path(file: &str) -> String {
+    match std::env::var("STATE_DIRECTORY") {
+        Ok(dir) if std::path::Path::new(file).is_relative() => format!("{}/{}", dir, file),
+        _ => file.to_string(),
+    }
+}
+
+fn parse_receptacle(id: &str) -> Option<(u8, u8, u8)> {
+    let id : Vec<u8> = id.split('.').map(|n| n.parse::<u8>().ok()).collect::<Option<Vec<u8>>>()?;
+    match id.as_slice() {
+        [pdu, branch, receptacle] => Some((*pdu, *branch, *receptacle)),
+        _ => None,
+    }
+}
+
+fn get_intervals_config(pdu: &ini::Properties) -> Intervals {
+    let mut receptacles = std::collections::HashMap::new();
+
+    for (key, value) in pdu.iter() {
+        if let Some(id) = key.strip_prefix("receptacle-interval-") {
+            let id = parse_receptacle(id).unwrap_or_else(|| {
+                eprintln!("Invalid receptacle in PDU {}, expected receptacle-interval-pdu.branch.receptacle", key);
+                std::process::exit(1);
+            });
+            receptacles.insert(id, parse_interval(key, value));
+        }
+    }
+
+    Intervals {
+        events: parse_interval("events-interval", pdu.get("events-interval").unwrap_or("3")),
+        pdu: parse_interval("pdu-interval", pdu.get("pdu-interval").unwrap_or("30")),
+        branch: parse_interval("branch-interval", pdu.get("branch-interval").unwrap_or("30")),
+        default_receptacle: parse_interval("receptacle-interval", pdu.get("receptacle-interval").unwrap_or("30")),
+        receptacles: receptacles,
+        adaptive: get_adaptive_config(pdu),
+    }
+}
+
+/* group-<name> = 1.1.1 1.1.2:10 1.2.1, the optional suffix overrides sequence-delay after that step */
+fn get_groups_config(pdu: &ini::Properties) -> Vec<sequence::Group> {
+    let mut groups = Vec::new();
+    let delay = match pdu.get("sequence-delay").unwrap_or("2").parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(e) => {
+            eprintln!("Failed to parse PDU sequence-delay in config: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    for (key, value) in pdu.iter() {
+        let name = match key.strip_prefix("group-") {
+            Some(name) => name,
+            None => continue,
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            eprintln!("Invalid group name in PDU {}, expected [a-zA-Z0-9_-]", key);
+            std::process::exit(1);
+        }
+
+        let mut steps = Vec::new();
+        for step in value.split_whitespace() {
+            let (id, step_delay) = match step.split_once(':') {
+                Some((id, seconds)) => (id, seconds.parse::<u64>().ok().map(Duration::from_secs)),
+                None => (step, Some(delay)),
+            };
+
+            match (parse_receptacle(id), step_delay) {
+                (Some(receptacle), Some(delay)) => steps.push(sequence::Step { receptacle, delay }),
+                _ => {
+                    eprintln!("Invalid step \"{}\" in PDU {}, expected pdu.branch.receptacle[:seconds]", step, key);
+                    std::process::exit(1);
+                },
+            }
+        }
+
+        groups.push(sequence::Group { name: name.to_string(), steps });
+    }
+
+    groups
+}
+
+/* depends-1.1.3 = 1.1.1 1.1.2 powers 1.1.1 and 1.1.2 before 1.1.3 */
+fn get_dependencies_config(pdu: &ini::Properties) -> dependency::Dependencies {
+    let mut requires = std::collections::HashMap::new();
+
+    for (key, value) in pdu.iter() {
+        let id = match key.strip_prefix("depends-") {
+            Some(id) => id,
+            None => continue,
+        };
+
+        let receptacle = parse_receptacle(id).unwrap_or_else(|| {
+            eprintln!("Invalid receptacle in PDU {}, expected depends-pdu.branch.receptacle", key);
+            std::process::exit(1);
+        });
+        let prerequisites : Vec<(u8, u8, u8)> = value.split_whitespace().map(|id| parse_receptacle(id).unwrap_or_else(|| {
+            eprintln!("Invalid receptacle \"{}\" in PDU {}, expected pdu.branch.receptacle", id, key);
+            std::process::exit(1);
+        })).collect();
+        requires.insert(receptacle, prerequisites);
+    }
+
+    let mode = pdu.get("dependency-disable").unwrap_or("refuse").parse().unwrap_or_else(|e| {
+        eprintln!("Failed to parse PDU dependency-disable in config: {}", e);
+        std::process::exit(1);
+    });
+
+    dependency::Dependencies::new(requires, mode).unwrap_or_else(|e| {
+        eprintln!("Invalid PDU dependencies in config: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn get_pdu_config(cfg: &Ini, name: &str, pdu: &ini::Properties) -> PDUCfg {
+    PDUCfg {
+        name: name.to_string(),
+        address: pdu.get("address").expect("PDU address missing in config").to_string(),
+        username: pdu.get("username").expect("PDU username missing in config").to_string(),
+        password: pdu.get("password").expect("PDU password missing in config").to_string(),
+        unreachable_timeout: match pdu.get("unreachable-timeout").unwrap_or("600").parse::<u64>().expect("Failed to parse PDU unreachable-timeout in config") {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        },
+        max_requests: match pdu.get("max-requests").unwrap_or("4").parse::<usize>().expect("Failed to parse PDU max-requests in config") {
+            0 => 1,
+            requests => requests,
+        },
+        intervals: get_intervals_config(pdu),
+        desired_state: pdu.get("desired-state").map(state_path),
+        drift_action: pdu.get("desired-state-action").unwrap_or("alert").parse().unwrap_or_else(|e| {
+            eprintln!("Failed to parse PDU desired-state-action in config: {}", e);
+            std::process::exit(1);
+        }),
+        protected: pdu.get("protected").unwrap_or("").split_whitespace().map(|id| parse_receptacle(id).unwrap_or_else(|| {
+            eprintln!("Invalid receptacle \"{}\" in PDU protected, expected pdu.branch.receptacle", id);
+            std::process::exit(1);
+        })).collect(),
+        override_token: pdu.get("override-token").map(|t| t.to_string()),
+        groups: get_groups_config(pdu),
+        budget_mode: pdu.get("load-budget").unwrap_or("off").parse().unwrap_or_else(|e| {
+            eprintln!("Failed to parse PDU load-budget in config: {}", e);
+            std::process::exit(1);
+        }),
+        budget_queue_timeout: parse_interval("load-budget-queue-timeout", pdu.get("load-budget-queue-timeout").unwrap_or("300")),
+        dependencies: get_dependencies_config(pdu),
+        event_policy: std::sync::Arc::new(policy::EventPolicy::from_ini(cfg, name)),
+    }
+}
+
+/* either a single [PDU] section or any number of [PDU:name] sections */
+fn get_pdus_config(cfg: &Ini) -> Vec<PDUCfg> {
+    let mut pdus : Vec<PDUCfg> = Vec::new();
+
+    for (name, section) in cfg.iter() {
+        let name = match name.and_then(|n| n.strip_prefix("PDU:")) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        if name.is_empty() || name.starts_with("pdu-") || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            eprintln!("Invalid PDU name in config section [PDU:{}], expected [a-zA-Z0-9_-] and no pdu- prefix", name);
+            std::process::exit(1);
+        }
+
+        if pdus.iter().any(|p| p.name == name) {
+            eprintln!("Duplicate config section [PDU:{}]", name);
+            std::process::exit(1);
+        }
+
+        pdus.push(get_pdu_config(cfg, name, section));
+    }
+
+    match cfg.section(Some("PDU")) {
+        Some(_) if !pdus.is_empty() => {
+            eprintln!("[PDU] can not be combined with [PDU:name] sections");
+            std::process::exit(1);
+        },
+        Some(pdu) => { pdus.push(get_pdu_config(cfg, "", pdu)); },
+        None if pdus.is_empty() => {
+            eprintln!("PDU section missing in config");
+            std::process::exit(1);
+        },
+        None => {},
+    }
+
+    let names : Vec<&str> = pdus.iter().map(|p| p.name.as_str()).collect();
+    policy::check_sections(cfg, &names);
+
+    pdus
+}
+
+fn get_config(filename: &str) -> Cfg {
+    let cfg = match Ini::load_from_file(filename) {
+        Err(ini::Error::Io(e)) => {
+            eprintln!("Failed to load config file \"{}\": {}", filename, e);
+            std::process::exit(1);
+        },
+        Err(ini::Error::Parse(e)) => {
+            eprintln!("Failed to parse config file \"{}\": {}", filename, e.msg);
+            std::process::exit(1);
+        },
+        Ok(cfg) => cfg,
+    };
+
+    let mqtt = cfg.section(Some("MQTT")).expect("MQTT section mising in config");
+
+    Cfg {
+        mqtt_address: mqtt.get("address").expect("MQTT address missing in config").to_string(),
+        mqtt_port: mqtt.get("port").expect("MQTT port missing in config").parse::<u16>().expect("Failed to parse MQTT port in config"),
+        mqtt_username: mqtt.get("username").expect("MQTT username missing in config").to_string(),
+        mqtt_password: mqtt.get("password").expect("MQTT password missing in config").to_string(),
+        mqtt_clientname: mqtt.get("clientname").expect("MQTT client name missing in config").to_string(),
+        mqtt_prefix: mqtt.get("prefix").expect("MQTT prefix missing in config").to_string(),
+        mqtt_no_retained: std::str::FromStr::from_str(mqtt.get("avoid-retained").unwrap_or("false")).expect("Failed to parse avoid-retained"),
+        hass_discovery: std::str::FromStr::from_str(mqtt.get("homeassistant-discovery").unwrap_or("false")).expect("Failed to parse homeassistant-discovery"),
+        hass_prefix: mqtt.get("homeassistant-prefix").unwrap_or("homeassistant").to_string(),
+        units: match std::str::FromStr::from_str(mqtt.get("si-units").unwrap_or("false")).expect("Failed to parse si-units") {
+            true => mqttify::Units::SI,
+            false => mqttify::Units::Scaled,
+        },
+        state_format: mqtt.get("state-format").unwrap_or("fields").parse().unwrap_or_else(|e| {
+            eprintln!("Failed to parse state-format in config: {}", e);
+            std::process::exit(1);
+        }),
+
+        pdus: get_pdus_config(&cfg),
+
+        deadband: deadband::Deadband::from_ini(&cfg),
+        metrics_listen: cfg.section(Some("Prometheus")).and_then(|p| p.get("listen")).map(|l| l.to_string()),
+        influxdb: cfg.section(Some("InfluxDB")).map(|influx| influxdb::InfluxCfg {
+            target: influx.get("url").expect("InfluxDB url missing in config").parse().unwrap_or_else(|e| {
+                eprintln!("Failed to parse InfluxDB url in config: {}", e);
+                std::process::exit(1);
+            }),
+            token: influx.get("token").map(|t| t.to_string()),
+        }),
+    }
+}
+
+async fn retry_cmd(mpx: &liebert::MPX, pdu: u8, branch: u8, receptacle: u8, cmd: liebert::ReceptacleCmd) -> Result<(), liebert::MPXError> {
+    let mut test = mpx.receptacle_command(pdu, branch, receptacle, cmd).await;
 
     for _i in 0..3 {
         if test.is_ok() {
@@ -407,20 +1502,87 @@ async fn retry_cmd(mpx: &liebert::MPX, pdu: u8, branch: u8, receptacle: u8, cmd:
     if ! test.is_ok() {
         eprintln!("Failed to {:?} receptacle {} on branch {} on pdu {}", cmd, receptacle, branch, pdu);
     }
+
+    test
+}
+
+/* threshold in percent of the rating, either given as "80", "80%" or in ampere as "12.8A" */
+fn parse_threshold(value: &str, rating: f64) -> Result<u32, String> {
+    if let Some(amps) = value.strip_suffix('A') {
+        let amps = amps.trim().parse::<f64>().map_err(|e| format!("invalid current \"{}\": {}", value, e))?;
+        if rating <= 0.0 {
+            return Err("unknown rating".to_string());
+        }
+        if amps < 0.0 || amps > rating {
+            return Err(format!("{}A is outside of the rating of {}A", amps, rating));
+        }
+        return Ok((amps / rating * 100.0).round() as u32);
+    }
+
+    let percent = value.strip_suffix('%').unwrap_or(value).trim();
+    let percent = percent.parse::<u32>().map_err(|e| format!("invalid threshold \"{}\": {}", value, e))?;
+    if percent > 100 {
+        return Err(format!("{}% is outside of the rating", percent));
+    }
+
+    Ok(percent)
+}
+
+fn out_of_range<T>(_: T) -> String {
+    "value out of range".to_string()
+}
+
+fn set_threshold<T: TryFrom<u32>>(field: &mut T, threshold: u32) -> Result<(), String> {
+    *field = T::try_from(threshold).map_err(out_of_range)?;
+    Ok(())
+}
+
+/* low-current alarm < over-current warning <= over-current alarm, 0 disables the low-current alarm */
+fn check_thresholds(line: &str, low: u32, warning: u32, alarm: u32) -> Result<(), String> {
+    if warning > alarm {
+        return Err(format!("{}over-current warning threshold {}% exceeds alarm threshold {}%", line, warning, alarm));
+    }
+    if low > 0 && low >= warning {
+        return Err(format!("{}low-current alarm threshold {}% must be below warning threshold {}%", line, low, warning));
+    }
+
+    Ok(())
+}
+
+fn unsupported(setting: Setting, level: &str) -> Result<(), String> {
+    Err(format!("{} is not supported for {}", setting.command(), level))
 }
 
-async fn update_label(mpx: &liebert::MPX, pdu: u8, branch: u8, receptacle: u8, label: String) {
+async fn update_receptacle_settings(mpx: &liebert::MPX, pdu: u8, branch: u8, receptacle: u8, setting: Setting, value: &str) -> Result<(), String> {
     let info = mpx.get_info_receptacle(pdu, branch, receptacle).await;
     if info.is_err() {
         eprintln!("Failed fetch info for receptacle {}.{}.{}", pdu, branch, receptacle);
-        return;
+        return Err(format!("{:?}", info.err().unwrap()));
     }
-    let info = info.unwrap();
+    let mut settings = info.unwrap().settings;
+
+    match setting {
+        Setting::Label => { settings.label = value.to_string(); },
+        Setting::AssetTag1 => { settings.asset_tag_1 = value.to_string(); },
+        Setting::AssetTag2 => { settings.asset_tag_2 = value.to_string(); },
+        Setting::PowerOnDelay => {
+            let delay = value.parse::<u32>().map_err(|e| format!("invalid delay \"{}\": {}", value, e))?;
+            settings.power_on_delay = delay.try_into().map_err(out_of_range)?;
+        },
+        Setting::OverCurrentAlarmThreshold | Setting::OverCurrentWarningThreshold | Setting::LowCurrentAlarmThreshold => {
+            let branchinfo = mpx.get_info_branch(pdu, branch).await.map_err(|e| format!("{:?}", e))?;
+            let threshold = parse_threshold(value, branchinfo.hardware.rated_line_current as f64)?;
+
+            match setting {
+                Setting::OverCurrentAlarmThreshold => set_threshold(&mut settings.over_current_alarm_threshold, threshold)?,
+                Setting::OverCurrentWarningThreshold => set_threshold(&mut settings.over_current_warning_threshold, threshold)?,
+                _ => set_threshold(&mut settings.low_current_alarm_threshold, threshold)?,
+            }
 
-    let settings = liebert::ReceptacleSettings {
-        label: label,
-        ..info.settings
-    };
+            check_thresholds("", settings.low_current_alarm_threshold as u32, settings.over_current_warning_threshold as u32, settings.over_current_alarm_threshold as u32)?;
+        },
+        _ => { return unsupported(setting, "receptacles"); },
+    }
 
     let mut test = mpx.set_receptacle_settings(pdu, branch, receptacle, &settings).await;
 
@@ -433,36 +1595,602 @@ async fn update_label(mpx: &liebert::MPX, pdu: u8, branch: u8, receptacle: u8, l
     }
 
     if ! test.is_ok() {
-        eprintln!("Failed to update label for receptacle {}.{}.{}", pdu, branch, receptacle);
+        eprintln!("Failed to update {:?} for receptacle {}.{}.{}", setting, pdu, branch, receptacle);
+    }
+
+    test.map_err(|e| format!("{:?}", e))
+}
+
+async fn update_branch_settings(mpx: &liebert::MPX, pdu: u8, branch: u8, setting: Setting, value: &str) -> Result<(), String> {
+    let info = mpx.get_info_branch(pdu, branch).await;
+    if info.is_err() {
+        eprintln!("Failed fetch info for branch {}.{}", pdu, branch);
+        return Err(format!("{:?}", info.err().unwrap()));
+    }
+    let info = info.unwrap();
+    let rating = info.hardware.rated_line_current as f64;
+    let mut settings = info.settings;
+
+    match setting {
+        Setting::Label => { settings.label = value.to_string(); },
+        Setting::AssetTag1 => { settings.asset_tag_1 = value.to_string(); },
+        Setting::AssetTag2 => { settings.asset_tag_2 = value.to_string(); },
+        Setting::OverCurrentAlarmThreshold => set_threshold(&mut settings.over_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::OverCurrentWarningThreshold => set_threshold(&mut settings.over_current_warning_threshold, parse_threshold(value, rating)?)?,
+        Setting::LowCurrentAlarmThreshold => set_threshold(&mut settings.low_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        _ => { return unsupported(setting, "branches"); },
+    }
+
+    check_thresholds("", settings.low_current_alarm_threshold as u32, settings.over_current_warning_threshold as u32, settings.over_current_alarm_threshold as u32)?;
+
+    let mut test = mpx.set_branch_settings(pdu, branch, &settings).await;
+
+    for _i in 0..3 {
+        if test.is_ok() {
+            break;
+        }
+
+        test = mpx.set_branch_settings(pdu, branch, &settings).await;
+    }
+
+    if ! test.is_ok() {
+        eprintln!("Failed to update {:?} for branch {}.{}", setting, pdu, branch);
+    }
+
+    test.map_err(|e| format!("{:?}", e))
+}
+
+async fn update_pdu_settings(mpx: &liebert::MPX, pdu: u8, setting: Setting, value: &str) -> Result<(), String> {
+    let info = mpx.get_info_pdu(pdu).await;
+    if info.is_err() {
+        eprintln!("Failed fetch info for pdu {}", pdu);
+        return Err(format!("{:?}", info.err().unwrap()));
+    }
+    let info = info.unwrap();
+    let rating = info.hardware.rated_input_current as f64;
+    let mut settings = info.settings;
+
+    match setting {
+        Setting::Label => { settings.label = value.to_string(); },
+        Setting::AssetTag1 => { settings.asset_tag_1 = value.to_string(); },
+        Setting::AssetTag2 => { settings.asset_tag_2 = value.to_string(); },
+        Setting::L1LowCurrentAlarmThreshold => set_threshold(&mut settings.l1_low_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::L1OverCurrentAlarmThreshold => set_threshold(&mut settings.l1_over_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::L1OverCurrentWarningThreshold => set_threshold(&mut settings.l1_over_current_warning_threshold, parse_threshold(value, rating)?)?,
+        Setting::L2LowCurrentAlarmThreshold => set_threshold(&mut settings.l2_low_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::L2OverCurrentAlarmThreshold => set_threshold(&mut settings.l2_over_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::L2OverCurrentWarningThreshold => set_threshold(&mut settings.l2_over_current_warning_threshold, parse_threshold(value, rating)?)?,
+        Setting::L3LowCurrentAlarmThreshold => set_threshold(&mut settings.l3_low_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::L3OverCurrentAlarmThreshold => set_threshold(&mut settings.l3_over_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::L3OverCurrentWarningThreshold => set_threshold(&mut settings.l3_over_current_warning_threshold, parse_threshold(value, rating)?)?,
+        Setting::NOverCurrentAlarmThreshold => set_threshold(&mut settings.n_over_current_alarm_threshold, parse_threshold(value, rating)?)?,
+        Setting::NOverCurrentWarningThreshold => set_threshold(&mut settings.n_over_current_warning_threshold, parse_threshold(value, rating)?)?,
+        _ => { return unsupported(setting, "PDUs"); },
+    }
+
+    check_thresholds("L1 ", settings.l1_low_current_alarm_threshold as u32, settings.l1_over_current_warning_threshold as u32, settings.l1_over_current_alarm_threshold as u32)?;
+    check_thresholds("L2 ", settings.l2_low_current_alarm_threshold as u32, settings.l2_over_current_warning_threshold as u32, settings.l2_over_current_alarm_threshold as u32)?;
+    check_thresholds("L3 ", settings.l3_low_current_alarm_threshold as u32, settings.l3_over_current_warning_threshold as u32, settings.l3_over_current_alarm_threshold as u32)?;
+    check_thresholds("N ", 0, settings.n_over_current_warning_threshold as u32, settings.n_over_current_alarm_threshold as u32)?;
+
+    let mut test = mpx.set_pdu_settings(pdu, &settings).await;
+
+    for _i in 0..3 {
+        if test.is_ok() {
+            break;
+        }
+
+        test = mpx.set_pdu_settings(pdu, &settings).await;
+    }
+
+    if ! test.is_ok() {
+        eprintln!("Failed to update {:?} for pdu {}", setting, pdu);
     }
+
+    test.map_err(|e| format!("{:?}", e))
 }
 
-fn port_is_enabled(tasklist: &mut TaskList, pdu: u8, branch: u8, receptacle: u8) -> bool {
-    for task in tasklist {
-        if task.pdu == pdu && task.branch == branch && task.receptacle == receptacle {
-            match task.receptacle_state {
-                Some(state) => { return state; },
-                None => {},
+#[derive(Clone)]
+struct Publisher {
+    client: AsyncClient,
+    prefix: String,
+    no_retained: bool,
+    state_format: mqttify::StateFormat,
+    connected: std::sync::Arc<AtomicBool>,
+    /* the rest of a batch is lost after a failed send, but already cached */
+    state: std::sync::Arc<SchedulerState>,
+}
+
+impl Publisher {
+    async fn send(self: &Self, topic: String, payload: String, retained: bool) -> bool {
+        let retained = retained && !self.no_retained;
+        let request = self.client.publish(&topic, QoS::AtLeastOnce, retained, payload);
+
+        match tokio::time::timeout(MQTT_PUBLISH_TIMEOUT, request).await {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                eprintln!("Failed to publish {}: {}", topic, e);
+                false
+            },
+            Err(_) => {
+                eprintln!("Timeout while publishing {}", topic);
+                false
+            },
+        }
+    }
+
+    /* messages are dropped while disconnected, retained state is resent after reconnecting or once sending works again */
+    async fn publish(self: &Self, messages: MQTTMsgList) {
+        for msg in messages {
+            if !self.connected.load(Ordering::SeqCst) {
+                return;
+            }
+
+            /* per-field values are still kept for the other sinks */
+            if !self.state_format.fields() && mqttify::is_field(&msg.topic) {
+                continue;
+            }
+
+            if !self.send(format!("{}{}", self.prefix, msg.topic), msg.payload, msg.retained).await {
+                self.state.dirty.store(true, Ordering::SeqCst);
+                return;
+            }
+
+            if self.state.dirty.swap(false, Ordering::SeqCst) {
+                self.state.resync.notify_one();
+            }
+        }
+    }
+
+    /* messages with topics outside of the configured prefix */
+    async fn publish_unprefixed(self: &Self, messages: &MQTTMsgList) {
+        for msg in messages {
+            if !self.connected.load(Ordering::SeqCst) {
+                return;
+            }
+
+            if !self.send(msg.topic.clone(), msg.payload.clone(), msg.retained).await {
+                self.state.dirty.store(true, Ordering::SeqCst);
+                return;
             }
         }
     }
+}
 
-    false
+impl sink::Sink for Publisher {
+    fn write<'a>(self: &'a Self, messages: &'a MQTTMsgList) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(self.publish(messages.clone()))
+    }
 }
 
-fn is_ready(tasklist: &mut TaskList) -> bool {
-    for task in tasklist {
-        /* ignore tasks not involving PDU requests */
-        if task.pdu == 0 {
-            continue;
+/* per PDU scheduler state, used for the systemd notifications */
+struct SchedulerState {
+    address: String,
+    ready: AtomicBool,
+    /* not polled completely yet, because the PDU does not answer */
+    unreachable: AtomicBool,
+    expired: AtomicBool,
+    heartbeat: std::sync::Mutex<Instant>,
+    /* retained state needs to be resent after (re)connecting to the MQTT broker */
+    resync: Notify,
+    /* messages were lost since the last resync */
+    dirty: AtomicBool,
+}
+
+impl SchedulerState {
+    fn new(address: &str) -> Self {
+        SchedulerState {
+            address: address.to_string(),
+            ready: AtomicBool::new(false),
+            unreachable: AtomicBool::new(false),
+            expired: AtomicBool::new(false),
+            heartbeat: std::sync::Mutex::new(Instant::now()),
+            resync: Notify::new(),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    fn alive(self: &Self) -> () {
+        *self.heartbeat.lock().unwrap() = Instant::now();
+    }
+
+    fn stalled(self: &Self) -> bool {
+        self.heartbeat.lock().unwrap().elapsed() > WATCHDOG_STALL_TIMEOUT
+    }
+
+    /* an unreachable PDU must not hold back the start of the others */
+    fn started(self: &Self) -> bool {
+        self.ready.load(Ordering::SeqCst) || self.unreachable.load(Ordering::SeqCst)
+    }
+
+    fn status(self: &Self) -> String {
+        let status = if self.stalled() {
+            "stalled"
+        } else if self.ready.load(Ordering::SeqCst) {
+            "ready"
+        } else if self.unreachable.load(Ordering::SeqCst) {
+            "unreachable"
+        } else {
+            "starting"
+        };
+
+        format!("{}: {}", self.address, status)
+    }
+}
+
+/* prerequisites that are off in the order they have to be enabled, e.g. the switch before the server,
+ * followed by the receptacle itself, the budget is checked for all of them at once */
+fn enable_set(target: (u8, u8, u8), ctx: &PduContext, scheduler: &Scheduler) -> Vec<(u8, u8, u8)> {
+    let mut set : Vec<(u8, u8, u8)> = ctx.pducfg.dependencies.prerequisites(target.0, target.1, target.2)
+        .into_iter()
+        .filter(|(pdu, branch, receptacle)| !scheduler.port_is_enabled(*pdu, *branch, *receptacle))
+        .collect();
+    set.push(target);
+    set
+}
+
+/* enables a set whose budget is reserved, the reservation of receptacles that were not switched on is released */
+async fn enable_receptacles(target: (u8, u8, u8), set: &[(u8, u8, u8)], ctx: &PduContext, scheduler: &Scheduler) -> Result<(), String> {
+    let mut stored = Ok(());
+
+    for (i, (pdu, branch, receptacle)) in set.iter().copied().enumerate() {
+        let prerequisite = (pdu, branch, receptacle) != target;
+        if prerequisite {
+            println!("Enable Receptacle {}.{}.{} required by {}.{}.{}", pdu, branch, receptacle, target.0, target.1, target.2);
+        }
+
+        let result = retry_cmd(&ctx.mpx, pdu, branch, receptacle, liebert::ReceptacleCmd::Enable).await;
+        scheduler.reschedule_in(pdu, branch, receptacle, 5);
+        if let Err(e) = result {
+            for (pdu, branch, receptacle) in &set[i..] {
+                ctx.budget.release(*pdu, *branch, *receptacle);
+            }
+            return Err(if prerequisite {
+                format!("failed to enable prerequisite {}.{}.{}: {:?}", pdu, branch, receptacle, e)
+            } else {
+                format!("{:?}", e)
+            });
+        }
+
+        /* the receptacle is switched on either way, so the remaining ones follow */
+        if let Err(e) = ctx.desired.set(pdu, branch, receptacle, true) {
+            stored = Err(if prerequisite { format!("prerequisite {}.{}.{}: {}", pdu, branch, receptacle, e) } else { e });
+        }
+    }
+
+    stored
+}
+
+/* dependents that are on either prevent disabling or are disabled first, returns the disabled ones in order */
+async fn disable_dependents(target: (u8, u8, u8), ctx: &PduContext, scheduler: &Scheduler) -> Result<Vec<(u8, u8, u8)>, String> {
+    let mut enabled = Vec::new();
+    for r in ctx.pducfg.dependencies.dependents(target.0, target.1, target.2) {
+        if scheduler.port_is_enabled(r.0, r.1, r.2) {
+            enabled.push(r);
+        }
+    }
+
+    if enabled.is_empty() {
+        return Ok(enabled);
+    }
+
+    if ctx.pducfg.dependencies.mode == dependency::DisableMode::Refuse {
+        let names : Vec<String> = enabled.iter().map(|(p, b, r)| format!("{}.{}.{}", p, b, r)).collect();
+        eprintln!("Rejected disable for receptacle {}.{}.{}: required by {}", target.0, target.1, target.2, names.join(", "));
+        return Err(format!("required by {}", names.join(", ")));
+    }
+
+    /* protected dependents would otherwise stop the cascade halfway */
+    for (pdu, branch, receptacle) in &enabled {
+        ctx.protection.check(*pdu, *branch, *receptacle).map_err(|e| format!("dependent {}.{}.{}: {}", pdu, branch, receptacle, e))?;
+    }
+
+    for (pdu, branch, receptacle) in enabled.iter().copied() {
+        println!("Disable Receptacle {}.{}.{} depending on {}.{}.{}", pdu, branch, receptacle, target.0, target.1, target.2);
+        ctx.protection.consume(pdu, branch, receptacle);
+        let result = retry_cmd(&ctx.mpx, pdu, branch, receptacle, liebert::ReceptacleCmd::Disable).await;
+        scheduler.reschedule_in(pdu, branch, receptacle, 5);
+        result.map_err(|e| format!("failed to disable dependent {}.{}.{}: {:?}", pdu, branch, receptacle, e))?;
+        ctx.desired.set(pdu, branch, receptacle, false).map_err(|e| format!("dependent {}.{}.{}: {}", pdu, branch, receptacle, e))?;
+    }
+
+    Ok(enabled)
+}
+
+/* enables a receptacle after the off-time of a cycle or policy action, followed by the dependents disabled along with it,
+ * in queue mode each waits for the budget until the queue timeout */
+async fn reenable(target: (u8, u8, u8), cascaded: Vec<(u8, u8, u8)>, ctx: &PduContext, scheduler: &Scheduler) -> Result<(), String> {
+    let deadline = Instant::now() + ctx.budget.queue_timeout;
+    let set = enable_set(target, ctx, scheduler);
+    ctx.budget.wait(&set, deadline, BUDGET_QUEUE_INTERVAL).await?;
+    println!("Re-enable Receptacle {}.{}.{}", target.0, target.1, target.2);
+    enable_receptacles(target, &set, ctx, scheduler).await?;
+
+    for dependent in cascaded.into_iter().rev() {
+        let (pdu, branch, receptacle) = dependent;
+        ctx.budget.wait(&[dependent], deadline, BUDGET_QUEUE_INTERVAL).await.map_err(|e| format!("dependent {}.{}.{}: {}", pdu, branch, receptacle, e))?;
+        println!("Re-enable Receptacle {}.{}.{} depending on {}.{}.{}", pdu, branch, receptacle, target.0, target.1, target.2);
+        enable_receptacles(dependent, &[dependent], ctx, scheduler).await.map_err(|e| format!("dependent {}.{}.{}: {}", pdu, branch, receptacle, e))?;
+    }
+
+    Ok(())
+}
+
+/* every command is counted and answered on its result topic exactly once */
+async fn finish_command(query: &Query, result: &Result<(), String>, ctx: &PduContext, scheduler: &Scheduler) -> () {
+    scheduler.stats.record_command(query.cmd.as_ref().map_or("invalid", |c| c.name()), result.is_ok());
+    ctx.publisher.publish(vec![query.result(result)]).await;
+}
+
+/* in queue mode an enable exceeding the budget waits in the background until the queue timeout
+ * and reports its result once done, other commands are not delayed meanwhile */
+async fn enable_command(query: &Query, ctx: &std::sync::Arc<PduContext>, scheduler: &std::sync::Arc<Scheduler>) -> Option<Result<(), String>> {
+    let target = (query.pdu, query.branch, query.receptacle);
+    let set = enable_set(target, ctx, scheduler);
+    let e = match ctx.budget.check(&set) {
+        Ok(()) => return Some(enable_receptacles(target, &set, ctx, scheduler).await),
+        Err(e) => e,
+    };
+
+    if ctx.budget.mode != budget::BudgetMode::Queue {
+        eprintln!("Rejected enable for receptacle {}: {}", query.target(), e);
+        return Some(Err(e));
+    }
+
+    eprintln!("Queued enable for receptacle {}: {}", query.target(), e);
+    let deadline = Instant::now() + ctx.budget.queue_timeout;
+    let (query, ctx, scheduler) = (query.clone(), ctx.clone(), scheduler.clone());
+    tokio::spawn(async move {
+        let result = match ctx.budget.wait(&set, deadline, BUDGET_QUEUE_INTERVAL).await {
+            Ok(()) => enable_receptacles(target, &set, &ctx, &scheduler).await,
+            Err(e) => {
+                eprintln!("Rejected enable for receptacle {}: queue timeout expired, {}", query.target(), e);
+                Err(format!("queued enable timed out, {}", e))
+            },
+        };
+        finish_command(&query, &result, &ctx, &scheduler).await;
+    });
+
+    None
+}
+
+async fn disable_command(query: &Query, ctx: &PduContext, scheduler: &Scheduler) -> Result<(), String> {
+    if let Err(e) = ctx.protection.check(query.pdu, query.branch, query.receptacle) {
+        eprintln!("Rejected {} for receptacle {}: {}", query.cmd.as_ref().map_or("disable", |c| c.name()), query.target(), e);
+        return Err(e);
+    }
+    disable_dependents((query.pdu, query.branch, query.receptacle), ctx, scheduler).await?;
+    ctx.protection.consume(query.pdu, query.branch, query.receptacle);
+    let result = retry_cmd(&ctx.mpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Disable).await;
+    scheduler.reschedule_in(query.pdu, query.branch, query.receptacle, 5);
+    result.map_err(|e| format!("{:?}", e))?;
+    ctx.desired.set(query.pdu, query.branch, query.receptacle, false)
+}
+
+/* disables the receptacle and enables it again in the background after the off-time,
+ * followed by the dependents disabled with it, the result is published once all is done */
+async fn cycle_command(query: &Query, seconds: u32, ctx: &std::sync::Arc<PduContext>, scheduler: &std::sync::Arc<Scheduler>) -> Option<Result<(), String>> {
+    let target = (query.pdu, query.branch, query.receptacle);
+    if let Err(e) = ctx.protection.check(query.pdu, query.branch, query.receptacle) {
+        eprintln!("Rejected cycle for receptacle {}: {}", query.target(), e);
+        return Some(Err(e));
+    }
+    let cascaded = match disable_dependents(target, ctx, scheduler).await {
+        Ok(cascaded) => cascaded,
+        Err(e) => return Some(Err(e)),
+    };
+    ctx.desired.suspend(query.pdu, query.branch, query.receptacle, Duration::from_secs(seconds.into()) + DRIFT_GRACE);
+    ctx.protection.consume(query.pdu, query.branch, query.receptacle);
+    let result = retry_cmd(&ctx.mpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Disable).await;
+    scheduler.reschedule_in(query.pdu, query.branch, query.receptacle, 5);
+    if let Err(e) = result {
+        return Some(Err(format!("{:?}", e)));
+    }
+
+    let (query, ctx, scheduler) = (query.clone(), ctx.clone(), scheduler.clone());
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(seconds.into())).await;
+        let result = reenable(target, cascaded, &ctx, &scheduler).await;
+        if let Err(e) = &result {
+            eprintln!("Failed to re-enable receptacle {} after power-cycle: {}", query.target(), e);
+        }
+        finish_command(&query, &result, &ctx, &scheduler).await;
+    });
+
+    None
+}
+
+/* None for commands that continue in the background and report their result themselves */
+async fn handle_command(query: &Query, ctx: &std::sync::Arc<PduContext>, scheduler: &std::sync::Arc<Scheduler>) -> Option<Result<(), String>> {
+    match query.cmd {
+        Ok(ref cmd @ (Command::EnableAll | Command::DisableAll | Command::Cancel)) => {
+            eprintln!("Invalid command for {}: {} is only supported for groups", query.target(), cmd.name());
+            Some(Err(format!("{} is only supported for groups", cmd.name())))
+        },
+        Ok(ref cmd) if query.receptacle == 0 && !matches!(cmd, Command::Set(_)) => {
+            eprintln!("Invalid command for {}: {} is only supported for receptacles", query.target(), cmd.name());
+            Some(Err(format!("{} is only supported for receptacles", cmd.name())))
+        },
+        Ok(Command::Enable) => {
+            println!("Enable Receptacle {}.{}.{}", query.pdu, query.branch, query.receptacle);
+            enable_command(query, ctx, scheduler).await
+        },
+        Ok(Command::Disable) => {
+            println!("Disable Receptacle {}.{}.{}", query.pdu, query.branch, query.receptacle);
+            Some(disable_command(query, ctx, scheduler).await)
+        },
+        Ok(Command::Toggle) => {
+            println!("Toggle Receptacle {}.{}.{}", query.pdu, query.branch, query.receptacle);
+            if scheduler.port_is_enabled(query.pdu, query.branch, query.receptacle) {
+                Some(disable_command(query, ctx, scheduler).await)
+            } else {
+                enable_command(query, ctx, scheduler).await
+            }
+        },
+        Ok(Command::Identify) => {
+            let result = retry_cmd(&ctx.mpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Identify).await;
+            Some(result.map_err(|e| format!("{:?}", e)))
+        },
+        Ok(Command::Set(setting)) => {
+            let value = query.payload.clone().unwrap_or("".to_string());
+            let result = if query.branch == 0 {
+                println!("Set PDU {} {:?} to \"{}\"", query.pdu, setting, value);
+                update_pdu_settings(&ctx.mpx, query.pdu, setting, &value).await
+            } else if query.receptacle == 0 {
+                println!("Set Branch {}.{} {:?} to \"{}\"", query.pdu, query.branch, setting, value);
+                update_branch_settings(&ctx.mpx, query.pdu, query.branch, setting, &value).await
+            } else {
+                println!("Set Receptacle {}.{}.{} {:?} to \"{}\"", query.pdu, query.branch, query.receptacle, setting, value);
+                update_receptacle_settings(&ctx.mpx, query.pdu, query.branch, query.receptacle, setting, &value).await
+            };
+            scheduler.reschedule_in(query.pdu, query.branch, query.receptacle, 5);
+            Some(result)
+        },
+        Ok(Command::Cycle(seconds)) => {
+            println!("Power-cycle Receptacle {}.{}.{} with {} seconds off-time", query.pdu, query.branch, query.receptacle, seconds);
+            cycle_command(query, seconds, ctx, scheduler).await
+        },
+        Ok(Command::Override) => {
+            let token = query.payload.clone().unwrap_or("".to_string());
+            let result = ctx.protection.arm(query.pdu, query.branch, query.receptacle, &token);
+            match &result {
+                Ok(()) => println!("Override protection of Receptacle {}.{}.{}", query.pdu, query.branch, query.receptacle),
+                Err(e) => eprintln!("Rejected override for receptacle {}: {}", query.target(), e),
+            }
+            Some(result)
+        },
+        Err(ref e) => {
+            eprintln!("Invalid command for {}: {}", query.target(), e);
+            Some(Err(e.clone()))
+        },
+    }
+}
+
+fn handle_group_command(query: &Query, group: &str, sequencer: &std::sync::Arc<sequence::Sequencer>) -> Result<(), String> {
+    let result = match query.cmd {
+        Ok(Command::EnableAll) => {
+            println!("Enable all receptacles of group {}", group);
+            sequencer.start(group, true)
+        },
+        Ok(Command::DisableAll) => {
+            println!("Disable all receptacles of group {}", group);
+            sequencer.start(group, false)
+        },
+        Ok(Command::Cancel) => {
+            println!("Cancel sequence of group {}", group);
+            sequencer.cancel(group)
+        },
+        Ok(ref cmd) => Err(format!("{} is not supported for groups", cmd.name())),
+        Err(ref e) => Err(e.clone()),
+    };
+
+    if let Err(e) = &result {
+        eprintln!("Invalid command for group {}: {}", group, e);
+    }
+    result
+}
+
+/* polls a single PDU and handles its commands, independent of all other PDUs */
+async fn run_pdu(pducfg: PDUCfg, cfg: std::sync::Arc<Cfg>, publisher: Publisher, mut rx: mpsc::Receiver<Query>, state: std::sync::Arc<SchedulerState>, exporter: Option<std::sync::Arc<metrics::Exporter>>) {
+    let mut sinks : sink::SinkList = vec![std::sync::Arc::new(publisher.clone())];
+    if let Some(influxcfg) = &cfg.influxdb {
+        sinks.push(std::sync::Arc::new(influxdb::InfluxDB::new(influxcfg.clone(), &pducfg.name, cfg.units)));
+    }
+
+    let reachability = Reachability::new(pducfg.unreachable_timeout);
+    let ctx = std::sync::Arc::new(PduContext {
+        mpx: liebert::MPX::new(&pducfg.address, &pducfg.username, &pducfg.password),
+        desired: desired::DesiredState::load(pducfg.desired_state.clone(), pducfg.drift_action),
+        protection: protection::Protection::new(&pducfg.protected, pducfg.override_token.clone()),
+        budget: budget::LoadBudget::new(pducfg.budget_mode, pducfg.budget_queue_timeout),
+        pducfg,
+        cfg,
+        publisher,
+        sinks,
+    });
+    let mut backoff = RETRY_BACKOFF_MIN;
+    let mut failures = 0;
+
+    let receptacles = loop {
+        state.alive();
+
+        match ctx.mpx.get_receptacles().await {
+            Ok(receptacles) if receptacles.len() > 0 => break receptacles,
+            Ok(_) => eprintln!("Found PDU {} without any receptacles, maybe it's still initializing?", ctx.pducfg.address),
+            Err(e) => eprintln!("Failed to get receptacle list from PDU {}: {:?}", ctx.pducfg.address, e),
         }
 
-        if task.cache.is_none() {
-            return false;
+        failures += 1;
+        if failures == PDU_OFFLINE_THRESHOLD {
+            eprintln!("PDU {} is unreachable", ctx.pducfg.address);
+            state.unreachable.store(true, Ordering::SeqCst);
         }
+
+        /* keep the backoff below the stall timeout, the PDU is not stuck but waiting */
+        state.alive();
+        state.expired.store(reachability.expired(), Ordering::SeqCst);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(WATCHDOG_STALL_TIMEOUT / 2);
+    };
+    let tasklist = setup_tasklist(&ctx, &receptacles).await.unwrap();
+
+    /* Home Assistant discovery */
+    let mut discovery : MQTTMsgList = Vec::new();
+    if ctx.cfg.hass_discovery {
+        discovery = hass::Discovery::new(&ctx.cfg.hass_prefix, &ctx.cfg.mqtt_clientname, &ctx.cfg.mqtt_prefix, &ctx.pducfg.name, ctx.cfg.units, ctx.cfg.state_format).config(&receptacles);
     }
 
-    true
+    let scheduler = std::sync::Arc::new(Scheduler::new(tasklist, ctx.pducfg.max_requests, reachability));
+    scheduler.start(&ctx.sinks);
+    if let Some(exporter) = exporter {
+        exporter.register(&ctx.pducfg.name, scheduler.clone());
+    }
+
+    let sequencer = std::sync::Arc::new(sequence::Sequencer::new(ctx.clone(), scheduler.clone()));
+
+    let mut ready = false;
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            /* control commands received via MQTT, they do not wait for polling */
+            Some(query) = rx.recv() => {
+                let result = match &query.group {
+                    Some(group) => Some(handle_group_command(&query, group, &sequencer)),
+                    None => handle_command(&query, &ctx, &scheduler).await,
+                };
+                if let Some(result) = result {
+                    finish_command(&query, &result, &ctx, &scheduler).await;
+                }
+            },
+
+            /* resend retained state after (re)connecting to the MQTT broker, without delaying commands */
+            _ = state.resync.notified() => {
+                let publisher = ctx.publisher.clone();
+                let discovery = discovery.clone();
+                let retained = scheduler.get_retained();
+                tokio::spawn(async move {
+                    publisher.publish_unprefixed(&discovery).await;
+                    publisher.publish(retained).await;
+                });
+            },
+
+            _ = ticker.tick() => {
+                state.alive();
+                state.expired.store(scheduler.expired(), Ordering::SeqCst);
+
+                /* check if all data of this PDU has been polled once */
+                if !ready {
+                    ready = scheduler.is_ready();
+                    if ready {
+                        println!("Polled all data of PDU {} once", ctx.pducfg.address);
+                        state.ready.store(true, Ordering::SeqCst);
+                    }
+                    state.unreachable.store(!ready && scheduler.unreachable(), Ordering::SeqCst);
+                }
+            },
+        }
+    }
 }
 
 #[tokio::main]
@@ -475,45 +2203,129 @@ async fn main() {
     }
 
     /* Config */
-    let cfg = get_config(&args[1]);
+    let cfg = std::sync::Arc::new(get_config(&args[1]));
     let prefix = cfg.mqtt_prefix.clone();
     let no_retained = cfg.mqtt_no_retained;
+    let availability_topic = format!("{}/availability", prefix);
 
     /* MQTT */
-    let mut mqttoptions = MqttOptions::new(cfg.mqtt_clientname, cfg.mqtt_address, cfg.mqtt_port);
+    let mut mqttoptions = MqttOptions::new(cfg.mqtt_clientname.clone(), cfg.mqtt_address.clone(), cfg.mqtt_port);
     mqttoptions.set_keep_alive(Duration::from_secs(5));
-    mqttoptions.set_credentials(cfg.mqtt_username, cfg.mqtt_password);
+    mqttoptions.set_credentials(cfg.mqtt_username.clone(), cfg.mqtt_password.clone());
     mqttoptions.set_transport(rumqttc::Transport::Tls(rumqttc::TlsConfiguration::default()));
+    mqttoptions.set_last_will(LastWill::new(&availability_topic, "offline", QoS::AtLeastOnce, !no_retained));
     let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
+    let connected = std::sync::Arc::new(AtomicBool::new(false));
 
-    /* PDU */
-    let mpx = liebert::MPX::new(&cfg.pdu_address, &cfg.pdu_username, &cfg.pdu_password);
-    let refmpx = std::sync::Arc::new(mpx);
-    let receptacles = refmpx.clone().get_receptacles().await.expect("Failed to get receptacle list");
-    if receptacles.len() < 1 {
-        eprintln!("Found PDU without any receptacles, maybe it's still initializing?");
-        std::process::exit(1);
+    /* Prometheus */
+    let exporter = cfg.metrics_listen.as_ref().map(|_| std::sync::Arc::new(metrics::Exporter::new(cfg.units)));
+    if let (Some(exporter), Some(address)) = (&exporter, &cfg.metrics_listen) {
+        tokio::spawn(exporter.clone().serve(address.clone()));
     }
-    let mut tasklist = setup_tasklist(refmpx.clone(), &receptacles).await.unwrap();
-    tasklist.append_systemd_watchdog(refmpx.clone());
 
-    /* Register control topics */
-    let topic = format!("{}/+/+/+/control", cfg.mqtt_prefix);
-    client.subscribe(topic, QoS::AtMostOnce).await.expect("failed to subscribe control topic");
+    /* PDUs, each one with its own scheduler sharing the MQTT client */
+    let mut routes : Vec<(String, mpsc::Sender<Query>)> = Vec::new();
+    let mut states : Vec<std::sync::Arc<SchedulerState>> = Vec::new();
 
-    let (tx, mut rx) = mpsc::channel(256);
+    for pducfg in &cfg.pdus {
+        let pdu_prefix = format!("{}{}", prefix, pducfg.topic_prefix());
+        let (tx, rx) = mpsc::channel(256);
+        let state = std::sync::Arc::new(SchedulerState::new(&pducfg.address));
 
-    let mut ready = false;
+        let publisher = Publisher {
+            client: client.clone(),
+            prefix: pdu_prefix.clone(),
+            no_retained: no_retained,
+            state_format: cfg.state_format,
+            connected: connected.clone(),
+            state: state.clone(),
+        };
+
+        tokio::spawn(run_pdu(pducfg.clone(), cfg.clone(), publisher, rx, state.clone(), exporter.clone()));
 
+        routes.push((format!("{}/", pdu_prefix), tx));
+        states.push(state);
+    }
+
+    /* receptacle, branch and PDU level */
+    let mut control_topics : Vec<String> = Vec::new();
+    for (route, _) in &routes {
+        control_topics.push(format!("{}+/+/+/control", route));
+        control_topics.push(format!("{}+/+/control", route));
+        control_topics.push(format!("{}+/control", route));
+    }
+
+    let eventclient = client.clone();
+    let eventconnected = connected.clone();
+    let eventstates = states.clone();
     tokio::spawn(async move {
+        let mut backoff = MQTT_RECONNECT_MIN;
+
         loop {
-            let notification = eventloop.poll().await.unwrap();
+            let notification = match eventloop.poll().await {
+                Ok(notification) => notification,
+                Err(e) => {
+                    if eventconnected.swap(false, Ordering::SeqCst) {
+                        eprintln!("Lost connection to MQTT broker: {}", e);
+                    } else {
+                        eprintln!("Failed to connect to MQTT broker: {}", e);
+                    }
+
+                    /* next poll reconnects */
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MQTT_RECONNECT_MAX);
+                    continue;
+                },
+            };
+
             match notification {
                 rumqttc::Event::Incoming(pkg) => {
                     match pkg {
+                        rumqttc::v4::Packet::ConnAck(_) => {
+                            println!("Connected to MQTT broker");
+                            backoff = MQTT_RECONNECT_MIN;
+
+                            eventconnected.store(true, Ordering::SeqCst);
+
+                            /* the request queue may still be full of messages from before the reconnect,
+                             * so wait for it in the background while the eventloop keeps draining it */
+                            let client = eventclient.clone();
+                            let availability_topic = availability_topic.clone();
+                            let control_topics = control_topics.clone();
+                            let states = eventstates.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = client.publish(&availability_topic, QoS::AtLeastOnce, !no_retained, "online").await {
+                                    eprintln!("Failed to publish availability: {}", e);
+                                }
+
+                                /* subscriptions do not survive a clean session */
+                                for topic in &control_topics {
+                                    if let Err(e) = client.subscribe(topic, QoS::AtMostOnce).await {
+                                        eprintln!("Failed to subscribe control topic {}: {}", topic, e);
+                                    }
+                                }
+
+                                for state in &states {
+                                    state.dirty.store(false, Ordering::SeqCst);
+                                    state.resync.notify_one();
+                                }
+                            });
+                        },
                         rumqttc::v4::Packet::Publish(publishpkg) => {
-                            let query = parse_incoming_msg(publishpkg);
-                            tx.send(query).await.expect("failed to forward MQTT command");
+                            let route = routes.iter().find(|(route, _)| {
+                                publishpkg.topic.strip_prefix(route.as_str()).map_or(false, |t| t.starts_with("pdu-") || t.starts_with("group-"))
+                            });
+
+                            /* a busy PDU must not block commands for the other ones */
+                            let topic = publishpkg.topic.clone();
+                            match route.map(|(_, tx)| (tx, parse_incoming_msg(publishpkg))) {
+                                Some((tx, Some(query))) => {
+                                    if let Err(e) = tx.try_send(query) {
+                                        eprintln!("Dropping MQTT command: {}", e);
+                                    }
+                                },
+                                _ => eprintln!("Ignoring message on unexpected topic {}", topic),
+                            }
                         },
                         _ => {}
                     }
@@ -523,83 +2335,161 @@ async fn main() {
         }
     });
 
-    tokio::spawn(async move {
-        loop {
-            /* 1. check if we can send the ready signal to systemd */
-            if !ready {
-                ready = is_ready(&mut tasklist);
-                if ready {
-                    println!("Polled all PDU data once; notifying to ready state...");
-                    let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]);
-                }
-            }
-
-            /* 2. check if any high priority task needs to be run */
-            for task in &mut tasklist {
-                if task.priority != TaskPriority::HIGH {
-                    continue;
-                }
+    /* systemd notifications covering all PDU schedulers */
+    let mut ready = false;
+    let mut status = String::new();
+    let mut watchdog = Instant::now();
+
+    loop {
+        if !ready && states.iter().all(|s| s.started()) {
+            ready = true;
+            println!("Polled all reachable PDUs once; notifying to ready state...");
+            let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]);
+        }
 
-                if task.timed_out() {
-                    task.run().await.expect("failed to run task");
-                }
-            }
+        let current = states.iter().map(|s| s.status()).collect::<Vec<String>>().join(", ");
+        if current != status {
+            status = current;
+            let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Status(&status)]);
+        }
 
-            /* 3. handle control commands received via MQTT */
-            let recvq = rx.try_recv();
-            if recvq.is_ok() {
-                let query = recvq.unwrap();
-                match query.cmd {
-                    Some(Command::Enable) => {
-                        println!("Enable Receptacle {}.{}.{}", query.pdu, query.branch, query.receptacle);
-                        retry_cmd(&refmpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Enable).await;
-                        tasklist.reschedule_in(query.pdu, query.branch, query.receptacle, 5);
-                    },
-                    Some(Command::Disable) => {
-                        println!("Disable Receptacle {}.{}.{}", query.pdu, query.branch, query.receptacle);
-                        retry_cmd(&refmpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Disable).await;
-                        tasklist.reschedule_in(query.pdu, query.branch, query.receptacle, 5);
-                    },
-                    Some(Command::Toggle) => {
-                        println!("Toggle Receptacle {}.{}.{}", query.pdu, query.branch, query.receptacle);
-                        if port_is_enabled(&mut tasklist, query.pdu, query.branch, query.receptacle) {
-                            retry_cmd(&refmpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Disable).await;
-                        } else {
-                            retry_cmd(&refmpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Enable).await;
-                        }
-                        tasklist.reschedule_in(query.pdu, query.branch, query.receptacle, 5);
-                    },
-                    Some(Command::Identify) => {
-                        retry_cmd(&refmpx, query.pdu, query.branch, query.receptacle, liebert::ReceptacleCmd::Identify).await;
-                    },
-                    Some(Command::SetLabel) => {
-                        let label = query.payload.unwrap_or("".to_string());
-                        println!("Set Receptacle {}.{}.{} label to \"{}\"", query.pdu, query.branch, query.receptacle, label);
-                        update_label(&refmpx, query.pdu, query.branch, query.receptacle, label).await;
-                        tasklist.reschedule_in(query.pdu, query.branch, query.receptacle, 5);
-                    },
-                    None => { /* ignore */},
-                }
-            }
+        /* a single stuck PDU scheduler must not restart the others */
+        if watchdog.elapsed() >= WATCHDOG_INTERVAL && !states.iter().all(|s| s.stalled()) {
+            watchdog = Instant::now();
+            let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]);
+        }
 
-            /* 4. check if oldest task needs to be executed, otherwise sleep for a second */
-            let oldest = tasklist.get_oldest().unwrap();
-            if oldest.timed_out() {
-                let messages = oldest.run().await.expect("failed to run task");
-                for msg in messages {
-                    if no_retained {
-                        client.publish(format!("{}{}", prefix, msg.topic), QoS::AtLeastOnce, false, msg.payload).await.unwrap();
-                    } else {
-                        client.publish(format!("{}{}", prefix, msg.topic), QoS::AtLeastOnce, msg.retained, msg.payload).await.unwrap();
-                    }
-                }
-            } else {
-                sleep(Duration::from_secs(1));
-            }
+        if states.iter().all(|s| s.expired.load(Ordering::SeqCst)) {
+            eprintln!("All PDUs have been unreachable for too long, giving up");
+            std::process::exit(1);
         }
-    });
 
-    loop{
-        sleep(Duration::from_secs(60));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query() -> Query {
+        Query { cmd: Err("".to_string()), pdu: 1, branch: 1, receptacle: 1, payload: None, id: None, group: None }
+    }
+
+    fn json_command(text: &str) -> Query {
+        let mut query = query();
+        parse_json_command(text, &mut query);
+        query
+    }
+
+    fn text_command(text: &str) -> Query {
+        let mut query = query();
+        parse_text_command(text, &mut query);
+        query
+    }
+
+    #[test]
+    fn json_commands() {
+        assert!(matches!(json_command(r#"{"cmd":"enable"}"#).cmd, Ok(Command::Enable)));
+        assert!(matches!(json_command(r#"{"cmd":"cycle"}"#).cmd, Ok(Command::Cycle(CYCLE_DEFAULT_DELAY))));
+        assert!(matches!(json_command(r#"{"cmd":"cycle","delay":10}"#).cmd, Ok(Command::Cycle(10))));
+
+        let query = json_command(r#"{"cmd":"set-label","label":"Rack 3 switch","id":7}"#);
+        assert!(matches!(query.cmd, Ok(Command::Set(Setting::Label))));
+        assert_eq!(query.payload.as_deref(), Some("Rack 3 switch"));
+        assert_eq!(query.id.as_deref(), Some("7"));
+
+        let query = json_command(r#"{"cmd":"set-threshold","threshold":"l1-over-current-alarm","value":80}"#);
+        assert!(matches!(query.cmd, Ok(Command::Set(Setting::L1OverCurrentAlarmThreshold))));
+        assert_eq!(query.payload.as_deref(), Some("80"));
+
+        let query = json_command(r#"{"cmd":"override","token":"secret"}"#);
+        assert!(matches!(query.cmd, Ok(Command::Override)));
+        assert_eq!(query.payload.as_deref(), Some("secret"));
+
+        /* the PDU enforces the label length */
+        assert!(json_command(&format!(r#"{{"cmd":"set-label","label":"{}"}}"#, "x".repeat(40))).cmd.is_ok());
+    }
+
+    #[test]
+    fn invalid_json_commands() {
+        assert!(json_command("enable").cmd.is_err());
+        assert!(json_command(r#"{"cmd":"explode"}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"enable","unknown":1}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"enable","delay":10}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"enable","value":1}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"cycle","token":"secret"}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"override"}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"set-power-on-delay"}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"set-label","label":"a\nb"}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"set-power-on-delay","label":"x","value":5}"#).cmd.is_err());
+        assert!(json_command(r#"{"cmd":"set-threshold","threshold":"unknown","value":80}"#).cmd.is_err());
+        assert_eq!(json_command(r#"{"cmd":"set-threshold","value":80}"#).cmd.err().as_deref(), Some("missing field threshold"));
+    }
+
+    #[test]
+    fn text_commands() {
+        assert!(matches!(text_command("enable").cmd, Ok(Command::Enable)));
+        assert!(matches!(text_command("toggle").cmd, Ok(Command::Toggle)));
+        assert!(matches!(text_command("cycle").cmd, Ok(Command::Cycle(CYCLE_DEFAULT_DELAY))));
+        assert!(matches!(text_command("cycle 30").cmd, Ok(Command::Cycle(30))));
+        assert!(matches!(text_command(&format!("cycle {}", CYCLE_MAX_DELAY)).cmd, Ok(Command::Cycle(CYCLE_MAX_DELAY))));
+
+        let query = text_command("override secret");
+        assert!(matches!(query.cmd, Ok(Command::Override)));
+        assert_eq!(query.payload.as_deref(), Some("secret"));
+
+        assert!(text_command("explode").cmd.is_err());
+        assert!(text_command("cycle x").cmd.is_err());
+        assert!(text_command("cycle -1").cmd.is_err());
+        assert!(text_command(&format!("cycle {}", CYCLE_MAX_DELAY + 1)).cmd.is_err());
+        assert!(text_command("cycle 99999999999").cmd.is_err());
+        assert!(json_command(&format!(r#"{{"cmd":"cycle","delay":{}}}"#, CYCLE_MAX_DELAY + 1)).cmd.is_err());
+    }
+
+    #[test]
+    fn setting_commands() {
+        let query = text_command("set-power-on-delay 5");
+        assert!(matches!(query.cmd, Ok(Command::Set(Setting::PowerOnDelay))));
+        assert_eq!(query.payload.as_deref(), Some("5"));
+
+        let query = text_command("set-asset-tag-1 rack 3");
+        assert!(matches!(query.cmd, Ok(Command::Set(Setting::AssetTag1))));
+        assert_eq!(query.payload.as_deref(), Some("rack 3"));
+
+        let query = text_command("set-threshold l2-low-current-alarm 12.8A");
+        assert!(matches!(query.cmd, Ok(Command::Set(Setting::L2LowCurrentAlarmThreshold))));
+        assert_eq!(query.payload.as_deref(), Some("12.8A"));
+
+        assert!(text_command("set-threshold l4-over-current-alarm 80").cmd.is_err());
+        assert!(text_command("set-unknown 5").cmd.is_err());
+        assert!(text_command("set-label").cmd.is_err());
+    }
+
+    #[test]
+    fn groups() {
+        let cfg = Ini::load_from_str("[PDU]\nsequence-delay = 3\ngroup-rack_1 = 1.1.1 1.1.2:10 1.2.1\n").unwrap();
+        let groups = get_groups_config(cfg.section(Some("PDU")).unwrap());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "rack_1");
+        let steps : Vec<((u8, u8, u8), u64)> = groups[0].steps.iter().map(|s| (s.receptacle, s.delay.as_secs())).collect();
+        assert_eq!(steps, vec![((1, 1, 1), 3), ((1, 1, 2), 10), ((1, 2, 1), 3)]);
+
+        let cfg = Ini::load_from_str("[PDU]\ngroup-rack = 1.1.1\n").unwrap();
+        assert_eq!(get_groups_config(cfg.section(Some("PDU")).unwrap())[0].steps[0].delay, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(parse_threshold("80", 16.0), Ok(80));
+        assert_eq!(parse_threshold("80%", 16.0), Ok(80));
+        assert_eq!(parse_threshold("100", 16.0), Ok(100));
+        assert_eq!(parse_threshold("12.8A", 16.0), Ok(80));
+        assert_eq!(parse_threshold("16A", 16.0), Ok(100));
+        assert!(parse_threshold("101", 16.0).is_err());
+        assert!(parse_threshold("-1", 16.0).is_err());
+        assert!(parse_threshold("16.1A", 16.0).is_err());
+        assert!(parse_threshold("8A", 0.0).is_err());
+        assert!(parse_threshold("x", 16.0).is_err());
     }
 }
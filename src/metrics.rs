@@ -0,0 +1,372 @@
+use crate::MQTTMsgList;
+use crate::Scheduler;
+use crate::mqttify::Units;
+use crate::fields::{self, SensorDesc};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/* do not let a stuck client occupy a connection forever */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_MAX_LENGTH: usize = 8192;
+
+#[derive(Default)]
+struct PollStats {
+    count: u64,
+    errors: u64,
+    duration: f64,
+}
+
+/* daemon self-metrics of a single PDU */
+#[derive(Default)]
+pub struct Stats {
+    polls: Mutex<BTreeMap<&'static str, PollStats>>,
+    commands: Mutex<BTreeMap<(String, bool), u64>>,
+}
+
+impl Stats {
+    pub fn record_poll(self: &Self, level: &'static str, duration: Duration, success: bool) -> () {
+        let mut polls = self.polls.lock().unwrap();
+        let stats = polls.entry(level).or_default();
+        stats.count += 1;
+        stats.duration += duration.as_secs_f64();
+        if !success {
+            stats.errors += 1;
+        }
+    }
+
+    pub fn record_command(self: &Self, cmd: &str, success: bool) -> () {
+        *self.commands.lock().unwrap().entry((cmd.to_string(), success)).or_insert(0) += 1;
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/* metric families, each with its help, type and samples */
+#[derive(Default)]
+struct Families {
+    families: BTreeMap<String, (String, &'static str, Vec<String>)>,
+}
+
+impl Families {
+    fn add(self: &mut Self, name: String, help: &str, kind: &'static str, labels: &[(&str, &str)], value: f64) -> () {
+        let labels : Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape(v))).collect();
+        let family = self.families.entry(name.clone()).or_insert_with(|| (help.to_string(), kind, Vec::new()));
+        family.2.push(format!("{}{{{}}} {}", name, labels.join(","), value));
+    }
+
+    fn render(self: &Self) -> String {
+        let mut result = String::new();
+
+        for (name, (help, kind, samples)) in &self.families {
+            result.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+            for sample in samples {
+                result.push_str(sample);
+                result.push('\n');
+            }
+        }
+
+        result
+    }
+}
+
+/* numeric status value converted to its unit */
+pub struct Sample {
+    pub level: &'static str,
+    pub pdu: String,
+    pub branch: String,
+    pub receptacle: String,
+    /* l1, l2, l3 or n for per-line PDU values, empty otherwise */
+    pub line: String,
+    pub desc: SensorDesc,
+    pub value: f64,
+}
+
+impl Sample {
+    pub fn name(self: &Self) -> &'static str {
+        self.desc.2
+    }
+
+    /* e.g. liebert_receptacle_current_amperes */
+    fn family(self: &Self) -> String {
+        format!("liebert_{}_{}_{}", self.level, self.desc.2, fields::unit_name(self.desc.4))
+    }
+
+    /* e.g. Current utilization */
+    fn help(self: &Self) -> String {
+        let help = self.desc.2.replace('_', " ");
+        let mut chars = help.chars();
+        chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+    }
+
+    fn kind(self: &Self) -> &'static str {
+        if fields::is_counter(self.desc.4) { "counter" } else { "gauge" }
+    }
+
+    fn id(self: &Self) -> String {
+        format!("{}.{}.{}", self.pdu, self.branch, self.receptacle)
+    }
+}
+
+fn topic_regex() -> regex::Regex {
+    regex::Regex::new(r"^/pdu-(?P<pdu>\d+)(/branch-(?P<branch>\d+)(/receptacle-(?P<receptacle>\d+))?)?/(?P<group>status|settings)/(?P<leaf>[a-z0-9-]+)$").unwrap()
+}
+
+/* /pdu-1/branch-2/receptacle-3/status/current => ("1", "2", "3", "status", "current") */
+fn parse_topic(re: &regex::Regex, topic: &str) -> Option<(String, String, String, String, String)> {
+    let caps = re.captures(topic)?;
+    let get = |name| caps.name(name).map_or("".to_string(), |m| m.as_str().to_string());
+
+    Some((get("pdu"), get("branch"), get("receptacle"), get("group"), get("leaf")))
+}
+
+/* all known numeric status values, other messages are skipped */
+pub fn samples(messages: &MQTTMsgList, units: Units) -> Vec<Sample> {
+    let re = topic_regex();
+    let mut result = Vec::new();
+
+    for msg in messages {
+        let (pdu, branch, receptacle, group, leaf) = match parse_topic(&re, &msg.topic) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        if group != "status" {
+            continue;
+        }
+
+        let level = if branch.is_empty() { "pdu" } else if receptacle.is_empty() { "branch" } else { "receptacle" };
+        let desc = match fields::sensor(level, &format!("status/{}", leaf)) {
+            Some(desc) => desc,
+            None => continue,
+        };
+
+        /* l1-voltage => voltage with line l1 */
+        let line = match leaf.split_once('-') {
+            Some((line, _)) if line == "n" || (line.len() == 2 && line.starts_with('l')) => line,
+            _ => "",
+        };
+
+        let value = match msg.payload.parse::<f64>() {
+            Ok(value) => units.to_si(value, desc.3.into()),
+            Err(_) => continue,
+        };
+
+        result.push(Sample { level, pdu, branch, receptacle, line: line.to_string(), desc, value });
+    }
+
+    result
+}
+
+fn add_values(families: &mut Families, name: &str, messages: &MQTTMsgList, units: Units) -> () {
+    let re = topic_regex();
+    let mut labels : BTreeMap<String, String> = BTreeMap::new();
+
+    for msg in messages {
+        if let Some((pdu, branch, receptacle, group, leaf)) = parse_topic(&re, &msg.topic) {
+            if group == "settings" && leaf == "label" {
+                labels.insert(format!("{}.{}.{}", pdu, branch, receptacle), msg.payload.clone());
+            }
+        }
+    }
+
+    for sample in samples(messages, units) {
+        let label = labels.get(&sample.id()).map_or("", |l| l.as_str());
+        let mut series = vec![("name", name), ("pdu", sample.pdu.as_str())];
+        if !sample.branch.is_empty() {
+            series.push(("branch", sample.branch.as_str()));
+        }
+        if !sample.receptacle.is_empty() {
+            series.push(("receptacle", sample.receptacle.as_str()));
+        }
+        if !sample.line.is_empty() {
+            series.push(("line", sample.line.as_str()));
+        }
+        series.push(("label", label));
+
+        families.add(sample.family(), &sample.help(), sample.kind(), &series, sample.value);
+    }
+}
+
+fn add_stats(families: &mut Families, name: &str, stats: &Stats) -> () {
+    for (level, poll) in stats.polls.lock().unwrap().iter() {
+        let labels = [("name", name), ("level", *level)];
+        families.add("pdu_ctrl_polls_total".to_string(), "Polling requests", "counter", &labels, poll.count as f64);
+        families.add("pdu_ctrl_poll_errors_total".to_string(), "Failed polling requests", "counter", &labels, poll.errors as f64);
+        families.add("pdu_ctrl_poll_duration_seconds_total".to_string(), "Time spent in polling requests", "counter", &labels, poll.duration);
+    }
+
+    for ((cmd, success), count) in stats.commands.lock().unwrap().iter() {
+        let labels = [("name", name), ("cmd", cmd.as_str()), ("success", if *success { "true" } else { "false" })];
+        families.add("pdu_ctrl_commands_total".to_string(), "Executed control commands", "counter", &labels, *count as f64);
+    }
+}
+
+/* request line and headers, None if the client closes early or sends too much */
+async fn read_request(socket: &mut tokio::net::TcpStream) -> Option<String> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > REQUEST_MAX_LENGTH {
+            return None;
+        }
+        match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    Some(String::from_utf8_lossy(&request).into_owned())
+}
+
+/* only GET /metrics is served, other methods and paths get a 404 */
+fn is_metrics_request(request: &str) -> bool {
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => path.split('?').next() == Some("/metrics"),
+        _ => false,
+    }
+}
+
+/* serves the last known values of all registered PDUs */
+pub struct Exporter {
+    pdus: Mutex<Vec<(String, Arc<Scheduler>)>>,
+    units: Units,
+}
+
+impl Exporter {
+    pub fn new(units: Units) -> Self {
+        Exporter { pdus: Mutex::new(Vec::new()), units }
+    }
+
+    /* PDUs are registered once their receptacles have been discovered */
+    pub fn register(self: &Self, name: &str, scheduler: Arc<Scheduler>) -> () {
+        self.pdus.lock().unwrap().push((name.to_string(), scheduler));
+    }
+
+    fn render(self: &Self) -> String {
+        let pdus = self.pdus.lock().unwrap().clone();
+        let mut families = Families::default();
+
+        for (name, scheduler) in pdus {
+            add_values(&mut families, &name, &scheduler.get_cached(), self.units);
+            add_stats(&mut families, &name, &scheduler.stats);
+        }
+
+        families.render()
+    }
+
+    async fn handle(self: &Self, mut socket: tokio::net::TcpStream) -> () {
+        /* the whole request head has to arrive in time, not just its first bytes */
+        let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut socket)).await {
+            Ok(Some(request)) => request,
+            _ => return,
+        };
+
+        let (status, body) = if is_metrics_request(&request) {
+            ("200 OK", self.render())
+        } else {
+            ("404 Not Found", "not found\n".to_string())
+        };
+
+        let response = format!("HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+        let _ = tokio::time::timeout(REQUEST_TIMEOUT, socket.write_all(response.as_bytes())).await;
+    }
+
+    pub async fn serve(self: Arc<Self>, address: String) -> () {
+        let listener = match tokio::net::TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to listen for metrics requests on {}: {}", address, e);
+                std::process::exit(1);
+            },
+        };
+
+        loop {
+            let socket = match listener.accept().await {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    eprintln!("Failed to accept metrics request: {}", e);
+                    continue;
+                },
+            };
+
+            let exporter = self.clone();
+            tokio::spawn(async move {
+                exporter.handle(socket).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MQTTMsg;
+
+    fn msg(topic: &str, payload: &str) -> MQTTMsg {
+        MQTTMsg { topic: topic.to_string(), payload: payload.to_string(), retained: true }
+    }
+
+    #[test]
+    fn requests() {
+        assert!(is_metrics_request("GET /metrics HTTP/1.1\r\nHost: pdu\r\n\r\n"));
+        assert!(is_metrics_request("GET /metrics?name=rack HTTP/1.1\r\n\r\n"));
+        assert!(!is_metrics_request("GET / HTTP/1.1\r\n\r\n"));
+        assert!(!is_metrics_request("GET /metrics/x HTTP/1.1\r\n\r\n"));
+        assert!(!is_metrics_request("POST /metrics HTTP/1.1\r\n\r\n"));
+        assert!(!is_metrics_request("\r\n\r\n"));
+    }
+
+    #[test]
+    fn sample_values() {
+        let messages = vec![
+            msg("/pdu-1/status/l2-current", "1500"),
+            msg("/pdu-1/branch-2/status/current-available-to-alarm", "3000"),
+            msg("/pdu-1/status/line-frequency", "500"),
+            msg("/pdu-1/branch-2/receptacle-3/status/current-utilization", "125"),
+            msg("/pdu-1/branch-2/receptacle-3/status/power/unit", "mW"),
+            msg("/pdu-1/branch-2/receptacle-3/status/power-state", "on"),
+            msg("/pdu-1/branch-2/receptacle-3/settings/label", "switch"),
+        ];
+
+        let samples = samples(&messages, Units::Scaled);
+        assert_eq!(samples.len(), 3);
+        assert_eq!((samples[0].level, samples[0].line.as_str(), samples[0].name(), samples[0].value), ("pdu", "l2", "current", 1.5));
+        assert_eq!((samples[1].level, samples[1].line.as_str(), samples[1].name(), samples[1].value), ("pdu", "", "frequency", 50.0));
+        assert_eq!((samples[2].level, samples[2].id(), samples[2].value), ("receptacle", "1.2.3".to_string(), 12.5));
+
+        let samples = super::samples(&vec![msg("/pdu-1/status/input-power", "1.25")], Units::SI);
+        assert_eq!(samples[0].value, 1.25);
+    }
+
+    #[test]
+    fn rendering() {
+        let mut families = Families::default();
+        add_values(&mut families, "rack", &vec![
+            msg("/pdu-1/branch-1/receptacle-1/status/power", "2000"),
+            msg("/pdu-1/branch-1/receptacle-2/status/power", "3000"),
+            msg("/pdu-1/branch-1/receptacle-1/settings/label", "say \"hi\""),
+        ], Units::Scaled);
+
+        assert_eq!(families.render(), concat!(
+            "# HELP liebert_receptacle_power_watts Power\n",
+            "# TYPE liebert_receptacle_power_watts gauge\n",
+            "liebert_receptacle_power_watts{name=\"rack\",pdu=\"1\",branch=\"1\",receptacle=\"1\",label=\"say \\\"hi\\\"\"} 2\n",
+            "liebert_receptacle_power_watts{name=\"rack\",pdu=\"1\",branch=\"1\",receptacle=\"2\",label=\"\"} 3\n",
+        ));
+
+        let stats = Stats::default();
+        stats.record_command("enable", true);
+        stats.record_command("enable", true);
+        stats.record_command("cycle", false);
+        let mut families = Families::default();
+        add_stats(&mut families, "rack", &stats);
+        let rendered = families.render();
+        assert!(rendered.contains("pdu_ctrl_commands_total{name=\"rack\",cmd=\"enable\",success=\"true\"} 2\n"), "{}", rendered);
+        assert!(rendered.contains("pdu_ctrl_commands_total{name=\"rack\",cmd=\"cycle\",success=\"false\"} 1\n"), "{}", rendered);
+    }
+}
@@ -1,747 +1,351 @@
 use crate::MQTTMsgList;
 use crate::MQTTMsg;
-
-pub trait ToMQTT {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList;
+use crate::fields::{FieldDesc, Fields, Value};
+use serde::Serialize;
+use serde::ser::{Serializer, SerializeMap};
+
+/* format of numeric payloads, Scaled is the historic integer format, e.g. mA and mV */
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Units {
+    Scaled,
+    SI,
 }
 
-impl ToMQTT for liebert_mpx::PDUHardware {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/pem-model", prefix),
-            payload: format!("{:?}", self.pem_model),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/fw-version", prefix),
-            payload: format!("{}", self.fw_version),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/serial-number", prefix),
-            payload: format!("{}", self.serial_number),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/wiring-type", prefix),
-            payload: format!("{}", self.wiring_type),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/rated-input-voltage", prefix),
-            payload: format!("{}", self.rated_input_voltage*1000 as u32),
-            retained: true,
-        });
+impl Units {
+    /* value is given in the SI unit, scale converts it to the integer format */
+    fn payload(self: Self, value: f32, scale: f32) -> String {
+        match self {
+            Units::Scaled => format!("{}", (value * scale) as u32),
+            Units::SI => format!("{}", value),
+        }
+    }
 
-        result.push(MQTTMsg {
-            topic: format!("{}/rated-input-current", prefix),
-            payload: format!("{}", self.rated_input_current*1000 as u32),
-            retained: true,
-        });
+    pub fn unit(self: Self, unit: &str, scale: f32) -> String {
+        if self == Units::SI || scale == 1.0 {
+            return unit.to_string();
+        }
+
+        match (unit, scale as u32) {
+            ("kWh", 1000) => "Wh".to_string(),
+            ("%", 10) => "‰".to_string(),
+            (_, 1000) => format!("m{}", unit),
+            (_, 10) => format!("d{}", unit),
+            _ => format!("{}/{}", unit, scale),
+        }
+    }
 
-        result.push(MQTTMsg {
-            topic: format!("{}/rated-input-line-frequency", prefix),
-            payload: format!("{}", self.rated_input_line_frequency*10 as u32),
-            retained: true,
-        });
+    /* converts a published payload back to the SI unit */
+    pub fn to_si(self: Self, value: f64, scale: f64) -> f64 {
+        match self {
+            Units::Scaled => value / scale,
+            Units::SI => value,
+        }
+    }
 
-        result
+    /* value with its unit in a retained sub-topic, e.g. .../status/current/unit */
+    fn measurement(self: Self, topic: String, value: f32, scale: f32, unit: &str, retained: bool) -> MQTTMsgList {
+        vec![
+            MQTTMsg {
+                topic: format!("{}/unit", topic),
+                payload: self.unit(unit, scale),
+                retained: true,
+            },
+            MQTTMsg {
+                topic,
+                payload: self.payload(value, scale),
+                retained,
+            },
+        ]
     }
 }
 
-impl ToMQTT for liebert_mpx::PDUSettings {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/label", prefix),
-            payload: format!("{}", self.label),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/asset-tag-1", prefix),
-            payload: format!("{}", self.asset_tag_1),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/asset-tag-2", prefix),
-            payload: format!("{}", self.asset_tag_2),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/n-over-current-alarm-threshold", prefix),
-            payload: format!("{}", self.n_over_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/n-over-current-warning-threshold", prefix),
-            payload: format!("{}", self.n_over_current_warning_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-low-current-alarm-threshold", prefix),
-            payload: format!("{}", self.l1_low_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-over-current-alarm-threshold", prefix),
-            payload: format!("{}", self.l1_over_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-over-current-warning-threshold", prefix),
-            payload: format!("{}", self.l1_over_current_warning_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-low-current-alarm-threshold", prefix),
-            payload: format!("{}", self.l2_low_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-over-current-alarm-threshold", prefix),
-            payload: format!("{}", self.l2_over_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-over-current-warning-threshold", prefix),
-            payload: format!("{}", self.l2_over_current_warning_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-low-current-alarm-threshold", prefix),
-            payload: format!("{}", self.l3_low_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-over-current-alarm-threshold", prefix),
-            payload: format!("{}", self.l3_over_current_alarm_threshold),
-            retained: true,
-        });
+/* per-field topics, a JSON document per PDU, branch and receptacle on .../state, or both */
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum StateFormat {
+    Fields,
+    JSON,
+    Both,
+}
 
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-over-current-warning-threshold", prefix),
-            payload: format!("{}", self.l3_over_current_warning_threshold),
-            retained: true,
-        });
+impl StateFormat {
+    pub fn fields(self: Self) -> bool {
+        self != StateFormat::JSON
+    }
 
-        result
+    pub fn json(self: Self) -> bool {
+        self != StateFormat::Fields
     }
 }
 
-impl ToMQTT for liebert_mpx::PDUEvents {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-low-voltage", prefix),
-            payload: format!("{:?}", self.low_voltage_l1),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-low-voltage", prefix),
-            payload: format!("{:?}", self.low_voltage_l2),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-low-voltage", prefix),
-            payload: format!("{:?}", self.low_voltage_l3),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-over-current", prefix),
-            payload: format!("{:?}", self.over_current_l1),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-over-current", prefix),
-            payload: format!("{:?}", self.over_current_l2),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-over-current", prefix),
-            payload: format!("{:?}", self.over_current_l3),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/n-over-current", prefix),
-            payload: format!("{:?}", self.over_current_n),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-low-current", prefix),
-            payload: format!("{:?}", self.low_current_l1),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-low-current", prefix),
-            payload: format!("{:?}", self.low_current_l2),
-            retained: true,
-        });
+impl std::str::FromStr for StateFormat {
+    type Err = String;
 
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-low-current", prefix),
-            payload: format!("{:?}", self.low_current_l3),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/failure", prefix),
-            payload: format!("{:?}", self.failure),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/communication-fail", prefix),
-            payload: format!("{:?}", self.communication_fail),
-            retained: true,
-        });
-
-        result
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fields" => Ok(StateFormat::Fields),
+            "json" => Ok(StateFormat::JSON),
+            "both" => Ok(StateFormat::Both),
+            _ => Err(format!("unknown state format \"{}\", expected fields, json or both", s)),
+        }
     }
 }
 
-impl ToMQTT for liebert_mpx::PDUStatus {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/accumulated-energy", prefix),
-            payload: format!("{}", (self.accumulated_energy * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/input-power", prefix),
-            payload: format!("{}", (self.input_power * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-voltage", prefix),
-            payload: format!("{}", (self.voltage_l1_n * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-voltage", prefix),
-            payload: format!("{}", (self.voltage_l2_n * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-voltage", prefix),
-            payload: format!("{}", (self.voltage_l3_n * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-current", prefix),
-            payload: format!("{}", (self.current_l1 * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-current", prefix),
-            payload: format!("{}", (self.current_l2 * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-current", prefix),
-            payload: format!("{}", (self.current_l2 * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/n-current", prefix),
-            payload: format!("{}", (self.current_n * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-current-available-to-alarm", prefix),
-            payload: format!("{}", (self.current_available_to_alarm_l1 * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-current-available-to-alarm", prefix),
-            payload: format!("{}", (self.current_available_to_alarm_l2 * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-current-available-to-alarm", prefix),
-            payload: format!("{}", (self.current_available_to_alarm_l3 * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l1-current-utilization", prefix),
-            payload: format!("{}", (self.current_utilization_l1 * 10.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l2-current-utilization", prefix),
-            payload: format!("{}", (self.current_utilization_l2 * 10.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/l3-current-utilization", prefix),
-            payload: format!("{}", (self.current_utilization_l3 * 10.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/line-frequency", prefix),
-            payload: format!("{}", (self.line_frequency * 10.0) as u32),
-            retained: false,
-        });
+/* status, events, settings and hardware values, e.g. /pdu-1/branch-2/status/current */
+pub fn is_field(topic: &str) -> bool {
+    let re = regex::Regex::new(r"^/pdu-\d+(/branch-\d+(/receptacle-\d+)?)?/(status|events|settings|hardware)/").unwrap();
+    re.is_match(topic)
+}
 
-        result
+/* value of a field in the JSON document, numbers with unit in the configured units like on their topics */
+struct DocumentValue<'a>(&'a Value, f32, &'a str, Units);
+
+impl Serialize for DocumentValue<'_> {
+    fn serialize<S: Serializer>(self: &Self, serializer: S) -> Result<S::Ok, S::Error> {
+        let DocumentValue(value, scale, unit, units) = self;
+        match value {
+            Value::Number(number) if !unit.is_empty() && *units == Units::Scaled => serializer.serialize_u32((number * scale) as u32),
+            Value::Number(number) => serializer.serialize_f32(*number),
+            Value::Text(text) => serializer.serialize_str(text),
+        }
     }
 }
 
-impl ToMQTT for liebert_mpx::PDUInfo {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.append(&mut self.status.to_mqtt(&format!("{}/status", prefix)));
-        result.append(&mut self.events.to_mqtt(&format!("{}/events", prefix)));
-        result.append(&mut self.settings.to_mqtt(&format!("{}/settings", prefix)));
-        result.append(&mut self.hardware.to_mqtt(&format!("{}/hardware", prefix)));
+/* values of one group, e.g. {"current": 1203, ...}, or the units of those that have one */
+struct Group<'a, T> {
+    info: &'a T,
+    group: &'a str,
+    units: Units,
+    unit: bool,
+}
 
-        result
+impl<T: Fields> Group<'_, T> {
+    fn fields(self: &Self) -> impl Iterator<Item = (&'static str, &'static FieldDesc<T>)> + '_ {
+        T::FIELDS.iter()
+            .filter_map(move |field| match field.0.split_once('/') {
+                Some((group, name)) if group == self.group => Some((name, field)),
+                _ => None,
+            })
+            .filter(move |(_, field)| !self.unit || !field.3.is_empty())
     }
 }
 
-impl ToMQTT for liebert_mpx::BranchHardware {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/brm-model", prefix),
-            payload: format!("{:?}", self.brm_model),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/fw-version", prefix),
-            payload: format!("{}", self.fw_version),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/serial-number", prefix),
-            payload: format!("{}", self.serial_number),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/receptacle-type", prefix),
-            payload: format!("{}", self.receptacle_type),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/capabilities", prefix),
-            payload: format!("{}", self.capabilities),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/line-source", prefix),
-            payload: format!("{}", self.line_source),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/rated-line-voltage", prefix),
-            payload: format!("{}", self.rated_line_voltage*1000),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/rated-line-current", prefix),
-            payload: format!("{}", self.rated_line_current*1000),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/rated-line-frequency", prefix),
-            payload: format!("{}", self.rated_line_frequency*10),
-            retained: true,
-        });
-
-        result
+impl<T: Fields> Serialize for Group<'_, T> {
+    fn serialize<S: Serializer>(self: &Self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (name, (_, get, scale, unit, _)) in self.fields() {
+            if self.unit {
+                map.serialize_entry(name, &self.units.unit(unit, *scale))?;
+            } else {
+                map.serialize_entry(name, &DocumentValue(&get(self.info), *scale, unit, self.units))?;
+            }
+        }
+        map.end()
     }
 }
 
-impl ToMQTT for liebert_mpx::BranchSettings {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/label", prefix),
-            payload: format!("{}", self.label),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/asset-tag-1", prefix),
-            payload: format!("{}", self.asset_tag_1),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/asset-tag-2", prefix),
-            payload: format!("{}", self.asset_tag_2),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/over-current-alarm-threshold", prefix),
-            payload: format!("{}", self.over_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/over-current-warning-threshold", prefix),
-            payload: format!("{}", self.over_current_warning_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/low-current-alarm-threshold", prefix),
-            payload: format!("{}", self.low_current_alarm_threshold),
-            retained: true,
-        });
+/* e.g. {"status": {"current": 1203, ...}, ..., "units": {"status": {"current": "mA", ...}}},
+ * groups without any unit, e.g. the events, are left out of the units */
+struct Document<'a, T> {
+    info: &'a T,
+    units: Units,
+    unit: bool,
+}
 
-        result
+impl<T: Fields> Serialize for Document<'_, T> {
+    fn serialize<S: Serializer>(self: &Self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for name in GROUPS {
+            let group = Group { info: self.info, group: name, units: self.units, unit: self.unit };
+            if !self.unit || group.fields().next().is_some() {
+                map.serialize_entry(name, &group)?;
+            }
+        }
+        if !self.unit {
+            map.serialize_entry("units", &Document { unit: true, ..*self })?;
+        }
+        map.end()
     }
 }
 
-impl ToMQTT for liebert_mpx::BranchEvents {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
+const GROUPS: [&str; 4] = ["status", "events", "settings", "hardware"];
 
-        result.push(MQTTMsg {
-            topic: format!("{}/low-voltage", prefix),
-            payload: format!("{:?}", self.low_voltage),
-            retained: true,
-        });
+/* per-field topics and the JSON document of a PDU, branch or receptacle, both generated from its field table */
+pub trait ToMQTT {
+    fn to_mqtt(self: &Self, prefix: &str, units: Units) -> MQTTMsgList;
+    fn to_state(self: &Self, prefix: &str, units: Units) -> MQTTMsg;
+}
 
-        result.push(MQTTMsg {
-            topic: format!("{}/over-current", prefix),
-            payload: format!("{:?}", self.over_current),
-            retained: true,
-        });
+impl<T: Fields> ToMQTT for T {
+    /* numbers with unit get a retained .../unit topic, only status values are not retained */
+    fn to_mqtt(self: &Self, prefix: &str, units: Units) -> MQTTMsgList {
+        let mut result : MQTTMsgList = Vec::new();
 
-        result.push(MQTTMsg {
-            topic: format!("{}/low-current", prefix),
-            payload: format!("{:?}", self.low_current),
-            retained: true,
-        });
+        for (name, get, scale, unit, _) in T::FIELDS {
+            let topic = format!("{}/{}", prefix, name);
+            let retained = !name.starts_with("status/");
+            match get(self) {
+                Value::Number(number) if !unit.is_empty() => result.append(&mut units.measurement(topic, number, *scale, unit, retained)),
+                Value::Number(number) => result.push(MQTTMsg { topic, payload: format!("{}", number), retained }),
+                Value::Text(text) => result.push(MQTTMsg { topic, payload: text, retained }),
+            }
+        }
 
-        result.push(MQTTMsg {
-            topic: format!("{}/failure", prefix),
-            payload: format!("{:?}", self.failure),
-            retained: true,
-        });
+        result
+    }
 
-        result.push(MQTTMsg {
-            topic: format!("{}/breaker-open", prefix),
-            payload: format!("{:?}", self.breaker_open),
+    fn to_state(self: &Self, prefix: &str, units: Units) -> MQTTMsg {
+        MQTTMsg {
+            topic: format!("{}/state", prefix),
+            payload: serde_json::to_string(&Document { info: self, units, unit: false }).unwrap_or_default(),
             retained: true,
-        });
-
-        result
+        }
     }
 }
 
-impl ToMQTT for liebert_mpx::BranchStatus {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/accumulated-energy", prefix),
-            payload: format!("{}", (self.accumulated_energy * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/voltage", prefix),
-            payload: format!("{}", (self.voltage * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/current", prefix),
-            payload: format!("{}", (self.current * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/current-available-to-alarm", prefix),
-            payload: format!("{}", (self.current_available_to_alarm * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/current-utilization", prefix),
-            payload: format!("{}", (self.current_utilization * 10.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power", prefix),
-            payload: format!("{}", (self.power * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/apparent-power", prefix),
-            payload: format!("{}", (self.apparent_power * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power-factor", prefix),
-            payload: format!("{}", self.apparent_power),
-            retained: false,
-        });
+#[derive(Serialize)]
+struct EventEntry {
+    pdu: u8,
+    branch: u8,
+    receptacle: u8,
+    event: String,
+    level: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action: Option<String>,
+}
 
-        result
+impl EventEntry {
+    fn new(event: &liebert_mpx::Event, timestamp: Option<u64>) -> Self {
+        EventEntry {
+            pdu: event.pdu,
+            branch: event.branch,
+            receptacle: event.receptacle,
+            event: format!("{:?}", event.event),
+            level: format!("{:?}", event.level),
+            timestamp: timestamp,
+            action: None,
+        }
     }
 }
 
-impl ToMQTT for liebert_mpx::BranchInfo {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.append(&mut self.status.to_mqtt(&format!("{}/status", prefix)));
-        result.append(&mut self.events.to_mqtt(&format!("{}/events", prefix)));
-        result.append(&mut self.settings.to_mqtt(&format!("{}/settings", prefix)));
-        result.append(&mut self.hardware.to_mqtt(&format!("{}/hardware", prefix)));
-
-        result
+/* single event log entry; timestamp is the UNIX time it has been noticed */
+pub fn event_to_mqtt(event: &liebert_mpx::Event, topic: &str, timestamp: u64) -> MQTTMsg {
+    MQTTMsg {
+        topic: topic.to_string(),
+        payload: serde_json::to_string(&EventEntry::new(event, Some(timestamp))).unwrap_or_default(),
+        retained: false,
     }
 }
 
-impl ToMQTT for liebert_mpx::ReceptacleHardware {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/receptacle-type", prefix),
-            payload: format!("{}", self.receptacle_type),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/line-source", prefix),
-            payload: format!("{}", self.line_source),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/capabilities", prefix),
-            payload: format!("{}", self.capabilities),
-            retained: true,
-        });
-
-        result
+/* event together with the action taken according to the configured policy */
+pub fn alert_to_mqtt(event: &liebert_mpx::Event, topic: &str, action: &str, timestamp: u64) -> MQTTMsg {
+    let entry = EventEntry {
+        action: Some(action.to_string()),
+        ..EventEntry::new(event, Some(timestamp))
+    };
+
+    MQTTMsg {
+        topic: topic.to_string(),
+        payload: serde_json::to_string(&entry).unwrap_or_default(),
+        retained: false,
     }
 }
 
-impl ToMQTT for liebert_mpx::ReceptacleSettings {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/label", prefix),
-            payload: format!("{}", self.label),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/asset-tag-1", prefix),
-            payload: format!("{}", self.asset_tag_1),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/asset-tag-2", prefix),
-            payload: format!("{}", self.asset_tag_2),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/over-current-alarm-threshold", prefix),
-            payload: format!("{}", self.over_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/over-current-warning-threshold", prefix),
-            payload: format!("{}", self.over_current_warning_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/low-current-alarm-threshold", prefix),
-            payload: format!("{}", self.low_current_alarm_threshold),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power-state", prefix),
-            payload: format!("{}", if self.power_state { "on" } else { "off" }),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power-control", prefix),
-            payload: format!("{}", if self.power_control { "on" } else { "off" }),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power-control-locked", prefix),
-            payload: format!("{}", if self.control_lock_state { "on" } else { "off" }),
-            retained: true,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power-on-delay", prefix),
-            payload: format!("{}", self.power_on_delay),
-            retained: true,
-        });
-
-        result
+/* receptacle power state differs from the one requested via control commands */
+pub fn drift_to_mqtt(pdu: u8, branch: u8, receptacle: u8, desired: bool, action: &str, topic: &str, timestamp: u64) -> MQTTMsg {
+    let entry = EventEntry {
+        pdu,
+        branch,
+        receptacle,
+        event: if desired { "ReceptacleUnexpectedOff" } else { "ReceptacleUnexpectedOn" }.to_string(),
+        level: "WARNING".to_string(),
+        timestamp: Some(timestamp),
+        action: Some(action.to_string()),
+    };
+
+    MQTTMsg {
+        topic: topic.to_string(),
+        payload: serde_json::to_string(&entry).unwrap_or_default(),
+        retained: false,
     }
 }
 
-impl ToMQTT for liebert_mpx::ReceptacleEvents {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
-
-        result.push(MQTTMsg {
-            topic: format!("{}/over-current", prefix),
-            payload: format!("{:?}", self.over_current),
-            retained: true,
-        });
+/* all alarms and warnings currently active on the PDU */
+pub fn active_alarms_to_mqtt(events: &liebert_mpx::EventList, topic: &str) -> MQTTMsg {
+    let alarms : Vec<EventEntry> = events.iter()
+        .filter(|e| e.level == liebert_mpx::EventLevel::ALARM || e.level == liebert_mpx::EventLevel::WARNING)
+        .map(|e| EventEntry::new(e, None))
+        .collect();
+
+    MQTTMsg {
+        topic: topic.to_string(),
+        payload: serde_json::to_string(&alarms).unwrap_or_default(),
+        retained: true,
+    }
+}
 
-        result.push(MQTTMsg {
-            topic: format!("{}/low-current", prefix),
-            payload: format!("{:?}", self.low_current),
-            retained: true,
-        });
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        result
+    struct Info {
+        current: f32,
+        factor: f32,
+        state: bool,
     }
-}
 
-impl ToMQTT for liebert_mpx::ReceptacleStatus {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
+    impl Fields for Info {
+        const FIELDS: &'static [FieldDesc<Self>] = &[
+            ("status/current", |i| Value::Number(i.current), 1000.0, "A", Some(("Current", "current"))),
+            ("status/power-factor", |i| Value::Number(i.factor), 1.0, "", None),
+            ("settings/power-state", |i| Value::Text(if i.state { "on" } else { "off" }.to_string()), 1.0, "", None),
+        ];
+    }
 
-        result.push(MQTTMsg {
-            topic: format!("{}/accumulated-energy", prefix),
-            payload: format!("{}", (self.accumulated_energy * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/voltage", prefix),
-            payload: format!("{}", (self.voltage * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/current", prefix),
-            payload: format!("{}", (self.current * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/current-available-to-alarm", prefix),
-            payload: format!("{}", (self.current_available_to_alarm * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/current-utilization", prefix),
-            payload: format!("{}", (self.current_utilization * 10.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power", prefix),
-            payload: format!("{}", (self.power * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/apparent-power", prefix),
-            payload: format!("{}", (self.apparent_power * 1000.0) as u32),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/power-factor", prefix),
-            payload: format!("{}", self.apparent_power),
-            retained: false,
-        });
-
-        result.push(MQTTMsg {
-            topic: format!("{}/current-crest-factor", prefix),
-            payload: format!("{}", self.current_crest_factor),
-            retained: false,
-        });
+    fn info() -> Info {
+        Info { current: 1.25, factor: 0.5, state: true }
+    }
 
-        result
+    fn payloads(messages: &MQTTMsgList) -> Vec<(&str, &str, bool)> {
+        messages.iter().map(|m| (m.topic.as_str(), m.payload.as_str(), m.retained)).collect()
     }
-}
 
-impl ToMQTT for liebert_mpx::ReceptacleInfo {
-    fn to_mqtt(self, prefix: &str) -> MQTTMsgList {
-        let mut result : MQTTMsgList = Vec::new();
+    #[test]
+    fn fields() {
+        assert_eq!(payloads(&info().to_mqtt("/pdu-1", Units::Scaled)), vec![
+            ("/pdu-1/status/current/unit", "mA", true),
+            ("/pdu-1/status/current", "1250", false),
+            ("/pdu-1/status/power-factor", "0.5", false),
+            ("/pdu-1/settings/power-state", "on", true),
+        ]);
+        assert_eq!(payloads(&info().to_mqtt("/pdu-1", Units::SI))[..2], [
+            ("/pdu-1/status/current/unit", "A", true),
+            ("/pdu-1/status/current", "1.25", false),
+        ]);
+    }
 
-        result.append(&mut self.status.to_mqtt(&format!("{}/status", prefix)));
-        result.append(&mut self.events.to_mqtt(&format!("{}/events", prefix)));
-        result.append(&mut self.settings.to_mqtt(&format!("{}/settings", prefix)));
-        result.append(&mut self.hardware.to_mqtt(&format!("{}/hardware", prefix)));
+    #[test]
+    fn document() {
+        let state = info().to_state("/pdu-1", Units::Scaled);
+        assert_eq!(state.topic, "/pdu-1/state");
+        assert!(state.retained);
+        assert_eq!(state.payload, concat!(
+            r#"{"status":{"current":1250,"power-factor":0.5},"events":{},"settings":{"power-state":"on"},"hardware":{},"#,
+            r#""units":{"status":{"current":"mA"}}}"#,
+        ));
+
+        let state = info().to_state("/pdu-1", Units::SI);
+        assert!(state.payload.starts_with(r#"{"status":{"current":1.25,"#), "{}", state.payload);
+        assert!(state.payload.ends_with(r#""units":{"status":{"current":"A"}}}"#), "{}", state.payload);
+    }
 
-        result
+    #[test]
+    fn unit_names() {
+        assert_eq!(Units::Scaled.unit("kWh", 1000.0), "Wh");
+        assert_eq!(Units::Scaled.unit("%", 10.0), "‰");
+        assert_eq!(Units::Scaled.unit("Hz", 10.0), "dHz");
+        assert_eq!(Units::Scaled.unit("%", 1.0), "%");
+        assert_eq!(Units::SI.unit("V", 1000.0), "V");
     }
 }
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Action {
+    Ignore,
+    Notify,
+    Disable,
+    /* disable and re-enable after the given number of seconds */
+    DisableReenable(u32),
+}
+
+impl std::str::FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let action = match (parts.next(), parts.next()) {
+            (Some("ignore"), None) => Action::Ignore,
+            (Some("notify"), None) => Action::Notify,
+            (Some("disable"), None) => Action::Disable,
+            (Some("disable-reenable"), Some(seconds)) => {
+                let seconds = seconds.parse::<u32>().map_err(|e| format!("invalid delay \"{}\": {}", seconds, e))?;
+                Action::DisableReenable(seconds)
+            },
+            _ => { return Err(format!("unknown action \"{}\"", s)); },
+        };
+
+        if parts.next().is_some() {
+            return Err(format!("unknown action \"{}\"", s));
+        }
+
+        Ok(action)
+    }
+}
+
+impl Action {
+    pub fn describe(self: &Self) -> String {
+        match self {
+            Action::Ignore => "ignore".to_string(),
+            Action::Notify => "notify".to_string(),
+            Action::Disable => "disable".to_string(),
+            Action::DisableReenable(seconds) => format!("disable-reenable {}", seconds),
+        }
+    }
+}
+
+/* ReceptacleOverCurrent + ALARM => receptacle-over-current-alarm */
+fn event_key(event: &liebert_mpx::Event) -> String {
+    let mut key = String::new();
+
+    for c in format!("{:?}", event.event).chars() {
+        if c.is_uppercase() && !key.is_empty() {
+            key.push('-');
+        }
+        key.extend(c.to_lowercase());
+    }
+
+    format!("{}-{}", key, format!("{:?}", event.level).to_lowercase())
+}
+
+type Rules = HashMap<String, Action>;
+
+fn parse_rules(section: &ini::Properties, name: &str) -> Rules {
+    let mut rules = Rules::new();
+
+    for (key, value) in section.iter() {
+        match value.parse::<Action>() {
+            Ok(action) => { rules.insert(key.to_string(), action); },
+            Err(e) => {
+                eprintln!("Failed to parse {} in config section [{}]: {}", key, name, e);
+                std::process::exit(1);
+            },
+        }
+    }
+
+    rules
+}
+
+/* [Policy:name:pdu.branch.receptacle] for named PDUs, [Policy:pdu.branch.receptacle] for a single [PDU] */
+fn split_section(name: &str) -> Option<(&str, &str)> {
+    let section = name.strip_prefix("Policy:")?;
+    Some(section.rsplit_once(':').unwrap_or(("", section)))
+}
+
+/* overrides for PDUs that are not configured would be silently ignored */
+pub fn check_sections(cfg: &ini::Ini, pdus: &[&str]) -> () {
+    for name in cfg.sections().flatten() {
+        if let Some((pdu, _)) = split_section(name) {
+            if !pdus.contains(&pdu) {
+                eprintln!("Config section [{}] does not refer to a configured PDU", name);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+pub struct EventPolicy {
+    default: Rules,
+    receptacles: HashMap<(u8, u8, u8), Rules>,
+}
+
+impl EventPolicy {
+    /* [Policy] sets the defaults for all PDUs, the receptacle sections of the PDU override them */
+    pub fn from_ini(cfg: &ini::Ini, pdu: &str) -> Self {
+        let mut default = Rules::new();
+        default.insert("receptacle-over-current-alarm".to_string(), Action::Disable);
+        default.insert("receptacle-over-current-warning".to_string(), Action::Ignore);
+
+        let mut receptacles = HashMap::new();
+
+        for (name, section) in cfg.iter() {
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if name == "Policy" {
+                default.extend(parse_rules(section, name));
+            } else if let Some((target, id)) = split_section(name) {
+                if target != pdu {
+                    continue;
+                }
+
+                let id = crate::parse_receptacle(id).unwrap_or_else(|| {
+                    eprintln!("Invalid receptacle in config section [{}], expected [Policy:pdu.branch.receptacle] or [Policy:name:pdu.branch.receptacle]", name);
+                    std::process::exit(1);
+                });
+                receptacles.insert(id, parse_rules(section, name));
+            }
+        }
+
+        EventPolicy { default, receptacles }
+    }
+
+    /* events without a rule are ignored */
+    pub fn get(self: &Self, event: &liebert_mpx::Event) -> Action {
+        self.rule((event.pdu, event.branch, event.receptacle), &event_key(event))
+    }
+
+    fn rule(self: &Self, receptacle: (u8, u8, u8), key: &str) -> Action {
+        if let Some(rules) = self.receptacles.get(&receptacle) {
+            if let Some(action) = rules.get(key) {
+                return *action;
+            }
+        }
+
+        *self.default.get(key).unwrap_or(&Action::Ignore)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(config: &str, pdu: &str) -> EventPolicy {
+        EventPolicy::from_ini(&ini::Ini::load_from_str(config).unwrap(), pdu)
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!("notify".parse::<Action>(), Ok(Action::Notify));
+        assert_eq!("disable-reenable 30".parse::<Action>(), Ok(Action::DisableReenable(30)));
+        assert!("disable-reenable".parse::<Action>().is_err());
+        assert!("disable-reenable x".parse::<Action>().is_err());
+        assert!("disable now".parse::<Action>().is_err());
+        assert!("explode".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn rules() {
+        let config = "[Policy]\nbranch-low-voltage-alarm = notify\n[Policy:1.1.1]\nreceptacle-over-current-alarm = notify\n[Policy:rack2:1.1.2]\nreceptacle-over-current-alarm = ignore\n";
+        let p = policy(config, "");
+        assert_eq!(p.rule((1, 1, 1), "receptacle-over-current-alarm"), Action::Notify);
+        assert_eq!(p.rule((1, 1, 2), "receptacle-over-current-alarm"), Action::Disable);
+        assert_eq!(p.rule((1, 1, 2), "branch-low-voltage-alarm"), Action::Notify);
+        assert_eq!(p.rule((1, 1, 2), "receptacle-over-current-warning"), Action::Ignore);
+        assert_eq!(p.rule((1, 1, 2), "pdu-low-voltage-alarm"), Action::Ignore);
+
+        /* overrides of other PDUs do not apply */
+        let p = policy(config, "rack2");
+        assert_eq!(p.rule((1, 1, 1), "receptacle-over-current-alarm"), Action::Disable);
+        assert_eq!(p.rule((1, 1, 2), "receptacle-over-current-alarm"), Action::Ignore);
+    }
+
+    #[test]
+    fn sections() {
+        assert_eq!(split_section("Policy:1.1.1"), Some(("", "1.1.1")));
+        assert_eq!(split_section("Policy:rack1:1.1.1"), Some(("rack1", "1.1.1")));
+        assert_eq!(split_section("Policy"), None);
+        assert_eq!(split_section("PDU:rack1"), None);
+    }
+}
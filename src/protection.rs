@@ -0,0 +1,105 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type Receptacle = (u8, u8, u8);
+
+/* an override only permits disabling within this time */
+const OVERRIDE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/* receptacles that must not be switched off without an explicit override, e.g. the network uplink */
+pub struct Protection {
+    receptacles: HashSet<Receptacle>,
+    token: Option<String>,
+    overrides: Mutex<HashMap<Receptacle, Instant>>,
+}
+
+impl Protection {
+    pub fn new(receptacles: &[Receptacle], token: Option<String>) -> Self {
+        Protection {
+            receptacles: receptacles.iter().copied().collect(),
+            token,
+            overrides: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_protected(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> bool {
+        self.receptacles.contains(&(pdu, branch, receptacle))
+    }
+
+    /* permits a single disable of the receptacle within OVERRIDE_TIMEOUT */
+    pub fn arm(self: &Self, pdu: u8, branch: u8, receptacle: u8, token: &str) -> Result<(), String> {
+        if !self.is_protected(pdu, branch, receptacle) {
+            return Err("receptacle is not protected".to_string());
+        }
+
+        match &self.token {
+            Some(expected) if expected == token => {
+                self.overrides.lock().unwrap().insert((pdu, branch, receptacle), Instant::now() + OVERRIDE_TIMEOUT);
+                Ok(())
+            },
+            Some(_) => Err("invalid override token".to_string()),
+            None => Err("no override token configured".to_string()),
+        }
+    }
+
+    /* a protected receptacle needs a pending override, checked before anything is switched */
+    pub fn check(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> Result<(), String> {
+        if !self.is_protected(pdu, branch, receptacle) {
+            return Ok(());
+        }
+
+        match self.overrides.lock().unwrap().get(&(pdu, branch, receptacle)) {
+            Some(until) if Instant::now() < *until => Ok(()),
+            _ => Err("receptacle is protected, send override first".to_string()),
+        }
+    }
+
+    /* the override only permits a single disable, called right before it is sent */
+    pub fn consume(self: &Self, pdu: u8, branch: u8, receptacle: u8) -> () {
+        self.overrides.lock().unwrap().remove(&(pdu, branch, receptacle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unprotected() {
+        let protection = Protection::new(&[(1, 1, 1)], Some("secret".to_string()));
+        assert!(!protection.is_protected(1, 1, 2));
+        assert_eq!(protection.check(1, 1, 2), Ok(()));
+        assert!(protection.arm(1, 1, 2, "secret").is_err());
+    }
+
+    #[test]
+    fn single_override() {
+        let protection = Protection::new(&[(1, 1, 1)], Some("secret".to_string()));
+        assert!(protection.check(1, 1, 1).is_err());
+        assert_eq!(protection.arm(1, 1, 1, "wrong"), Err("invalid override token".to_string()));
+        assert!(protection.check(1, 1, 1).is_err());
+
+        assert_eq!(protection.arm(1, 1, 1, "secret"), Ok(()));
+        /* rejected commands do not use the override up */
+        assert_eq!(protection.check(1, 1, 1), Ok(()));
+        assert_eq!(protection.check(1, 1, 1), Ok(()));
+
+        protection.consume(1, 1, 1);
+        assert!(protection.check(1, 1, 1).is_err());
+    }
+
+    #[test]
+    fn expired_override() {
+        let protection = Protection::new(&[(1, 1, 1)], Some("secret".to_string()));
+        protection.overrides.lock().unwrap().insert((1, 1, 1), Instant::now());
+        assert!(protection.check(1, 1, 1).is_err());
+    }
+
+    #[test]
+    fn without_token() {
+        let protection = Protection::new(&[(1, 1, 1)], None);
+        assert_eq!(protection.arm(1, 1, 1, ""), Err("no override token configured".to_string()));
+        assert!(protection.check(1, 1, 1).is_err());
+    }
+}
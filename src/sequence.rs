@@ -0,0 +1,179 @@
+use crate::{MQTTMsg, PduContext, Scheduler};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+#[derive(Clone,Debug)]
+pub struct Step {
+    pub receptacle: (u8, u8, u8),
+    /* wait time before the next step */
+    pub delay: Duration,
+}
+
+/* receptacles in power-on order, switched off in reverse order */
+#[derive(Clone,Debug)]
+pub struct Group {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+impl Group {
+    fn ordered(self: &Self, enable: bool) -> Vec<Step> {
+        let mut steps = self.steps.clone();
+        if !enable {
+            steps.reverse();
+        }
+        steps
+    }
+}
+
+#[derive(Serialize)]
+struct Progress<'a> {
+    cmd: &'a str,
+    step: usize,
+    steps: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    receptacle: Option<String>,
+    state: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/* runs group sequences of a single PDU in the background, at most one per group */
+pub struct Sequencer {
+    ctx: Arc<PduContext>,
+    scheduler: Arc<Scheduler>,
+    running: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+impl Sequencer {
+    pub fn new(ctx: Arc<PduContext>, scheduler: Arc<Scheduler>) -> Self {
+        Sequencer { ctx, scheduler, running: Mutex::new(HashMap::new()) }
+    }
+
+    /* progress of the running sequence on <prefix>/group-<name>/progress */
+    async fn progress(self: &Self, group: &str, progress: Progress<'_>) -> () {
+        let msg = MQTTMsg {
+            topic: format!("/group-{}/progress", group),
+            payload: serde_json::to_string(&progress).unwrap_or_default(),
+            retained: false,
+        };
+        self.ctx.publisher.publish(vec![msg]).await;
+    }
+
+    /* dependencies outside of the group are handled as for single commands */
+    async fn step(self: &Self, target: (u8, u8, u8), enable: bool) -> Result<(), String> {
+        let (pdu, branch, receptacle) = target;
+
+        if enable {
+            let set = crate::enable_set(target, &self.ctx, &self.scheduler);
+            self.ctx.budget.check(&set)?;
+            return crate::enable_receptacles(target, &set, &self.ctx, &self.scheduler).await;
+        }
+
+        self.ctx.protection.check(pdu, branch, receptacle)?;
+        crate::disable_dependents(target, &self.ctx, &self.scheduler).await?;
+        self.ctx.protection.consume(pdu, branch, receptacle);
+
+        let result = crate::retry_cmd(&self.ctx.mpx, pdu, branch, receptacle, liebert::ReceptacleCmd::Disable).await.map_err(|e| format!("{:?}", e));
+        self.scheduler.reschedule_in(pdu, branch, receptacle, 5);
+        result?;
+
+        self.ctx.desired.set(pdu, branch, receptacle, false)
+    }
+
+    /* failed steps are reported and skipped, cancelling takes effect before the next step */
+    async fn run(self: Arc<Self>, group: Group, enable: bool, cancel: Arc<Notify>) -> () {
+        let cmd = if enable { "enable-all" } else { "disable-all" };
+        let steps = group.ordered(enable);
+
+        let mut delay = Duration::from_secs(0);
+        for (i, step) in steps.iter().enumerate() {
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                _ = cancel.notified() => {
+                    println!("Cancelled {} of group {} before step {}", cmd, group.name, i + 1);
+                    self.progress(&group.name, Progress { cmd, step: i, steps: steps.len(), receptacle: None, state: "cancelled", error: None }).await;
+                    self.running.lock().unwrap().remove(&group.name);
+                    return;
+                },
+            }
+
+            let (pdu, branch, receptacle) = step.receptacle;
+            let result = self.step(step.receptacle, enable).await;
+            if let Err(e) = &result {
+                eprintln!("Failed {} of group {} for receptacle {}.{}.{}: {}", cmd, group.name, pdu, branch, receptacle, e);
+            }
+
+            let target = Some(format!("{}.{}.{}", pdu, branch, receptacle));
+            self.progress(&group.name, Progress { cmd, step: i + 1, steps: steps.len(), receptacle: target, state: "running", error: result.err() }).await;
+            delay = step.delay;
+        }
+
+        println!("Finished {} of group {}", cmd, group.name);
+        self.progress(&group.name, Progress { cmd, step: steps.len(), steps: steps.len(), receptacle: None, state: "done", error: None }).await;
+        self.running.lock().unwrap().remove(&group.name);
+    }
+
+    pub fn start(self: &Arc<Self>, name: &str, enable: bool) -> Result<(), String> {
+        let group = match self.ctx.pducfg.groups.iter().find(|g| g.name == name) {
+            Some(group) => group.clone(),
+            None => return Err(format!("unknown group \"{}\"", name)),
+        };
+
+        let mut running = self.running.lock().unwrap();
+        if running.contains_key(name) {
+            return Err("sequence already running, cancel it first".to_string());
+        }
+
+        let cancel = Arc::new(Notify::new());
+        running.insert(name.to_string(), cancel.clone());
+        tokio::spawn(self.clone().run(group, enable, cancel));
+
+        Ok(())
+    }
+
+    pub fn cancel(self: &Self, name: &str) -> Result<(), String> {
+        match self.running.lock().unwrap().get(name) {
+            Some(cancel) => {
+                cancel.notify_one();
+                Ok(())
+            },
+            None => Err("no sequence running".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group() -> Group {
+        Group {
+            name: "rack".to_string(),
+            steps: vec![
+                Step { receptacle: (1, 1, 1), delay: Duration::from_secs(10) },
+                Step { receptacle: (1, 1, 2), delay: Duration::from_secs(2) },
+                Step { receptacle: (1, 2, 1), delay: Duration::from_secs(2) },
+            ],
+        }
+    }
+
+    #[test]
+    fn order() {
+        let receptacles = |steps: Vec<Step>| steps.iter().map(|s| s.receptacle).collect::<Vec<_>>();
+        assert_eq!(receptacles(group().ordered(true)), vec![(1, 1, 1), (1, 1, 2), (1, 2, 1)]);
+        assert_eq!(receptacles(group().ordered(false)), vec![(1, 2, 1), (1, 1, 2), (1, 1, 1)]);
+    }
+
+    #[test]
+    fn progress() {
+        let running = Progress { cmd: "enable-all", step: 1, steps: 3, receptacle: Some("1.1.1".to_string()), state: "running", error: None };
+        assert_eq!(serde_json::to_string(&running).unwrap(), r#"{"cmd":"enable-all","step":1,"steps":3,"receptacle":"1.1.1","state":"running"}"#);
+
+        let cancelled = Progress { cmd: "disable-all", step: 2, steps: 3, receptacle: None, state: "cancelled", error: Some("x".to_string()) };
+        assert_eq!(serde_json::to_string(&cancelled).unwrap(), r#"{"cmd":"disable-all","step":2,"steps":3,"state":"cancelled","error":"x"}"#);
+    }
+}
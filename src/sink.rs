@@ -0,0 +1,15 @@
+use crate::MQTTMsgList;
+use std::future::Future;
+use std::pin::Pin;
+
+/* destination for polled values, topics are relative to the PDU */
+pub trait Sink: Send + Sync {
+    fn write<'a>(self: &'a Self, messages: &'a MQTTMsgList) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+    /* every polled value instead of only the changes passing the deadband, e.g. for time series */
+    fn every_sample(self: &Self) -> bool {
+        false
+    }
+}
+
+pub type SinkList = Vec<std::sync::Arc<dyn Sink>>;